@@ -3,11 +3,14 @@
 //! Tiny wrapper to send messages to the socket
 
 use std::fmt::Display;
+use std::fs::File;
 use std::io::{Read, Write};
 use std::os::unix::net::UnixStream;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
 use clap::{Parser, Subcommand, ValueEnum};
+use lxwengd::playlist::{self, Severity};
 
 #[derive(Parser)]
 #[command(
@@ -33,6 +36,19 @@ enum Command {
         #[arg(short = 'r', long = "resume")]
         resume: ResumeMode,
 
+        #[arg(
+            short = 's',
+            long = "shuffle",
+            help = "Play wallpapers in random order"
+        )]
+        shuffle: bool,
+
+        #[arg(
+            long = "strict",
+            help = "Refuse to load the playlist if it has any error"
+        )]
+        strict: bool,
+
         path: PathBuf,
     },
 
@@ -47,21 +63,52 @@ enum Command {
 
     #[command(about = "Pause a playlist")]
     Pause {
-        #[arg(short = 'c', long = "clear")]
+        #[arg(
+            short = 'c',
+            long = "clear",
+            help = "Terminate `linux-wallpaperengine`"
+        )]
         clear: bool,
+        #[arg(
+            short = 'f',
+            long = "freeze",
+            conflicts_with = "clear",
+            help = "Freeze `linux-wallpaperengine`, keeping the frame on screen"
+        )]
+        freeze: bool,
     },
 
     #[command(about = "Toggle play/pause for a playlist")]
     Toggle {
-        #[arg(short = 'c', long = "clear")]
+        #[arg(
+            short = 'c',
+            long = "clear",
+            help = "Terminate `linux-wallpaperengine`"
+        )]
         clear: bool,
+        #[arg(
+            short = 'f',
+            long = "freeze",
+            conflicts_with = "clear",
+            help = "Freeze `linux-wallpaperengine`, keeping the frame on screen"
+        )]
+        freeze: bool,
     },
 
     #[command(about = "Show LxWEngd status")]
     Status,
 
+    #[command(about = "Show the output of linux-wallpaperengine for a playlist")]
+    Logs {
+        #[arg(short = 'f', long = "follow", help = "Keep showing new output")]
+        follow: bool,
+    },
+
     #[command(about = "Quit LxWEngd")]
     Quit,
+
+    #[command(about = "Check a playlist for errors without loading it")]
+    Check { path: PathBuf },
 }
 
 #[derive(Clone, ValueEnum)]
@@ -81,36 +128,108 @@ impl Display for ResumeMode {
     }
 }
 
-fn main() {
+/// Names the pause mode sent to the daemon.
+fn pause_mode(clear: bool, freeze: bool) -> &'static str {
+    if clear {
+        "clear"
+    } else if freeze {
+        "freeze"
+    } else {
+        "keep"
+    }
+}
+
+/// Parses a playlist the same way the daemon does, and prints every issue found.
+///
+/// Fails if the playlist cannot be found, has any error, or has no valid commands.
+fn check(path: &Path) -> ExitCode {
+    let (found, file) = match playlist::find(path).and_then(|found| {
+        let file = File::open(&found)?;
+        Ok((found, file))
+    }) {
+        Ok(result) => result,
+        Err(err) => {
+            eprintln!("{}: {err}", path.to_string_lossy());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let parsed = playlist::check(&found, &file);
+    for issue in &parsed.issues {
+        println!("{issue}");
+    }
+    let errors = parsed
+        .issues
+        .iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .count();
+    let warnings = parsed.issues.len() - errors;
+    if parsed.commands.is_empty() {
+        println!("{} error: no valid commands", found.to_string_lossy());
+    }
+    println!(
+        "{}: {} commands, {errors} errors, {warnings} warnings",
+        found.to_string_lossy(),
+        parsed.commands.len()
+    );
+
+    if errors > 0 || parsed.commands.is_empty() {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn main() -> ExitCode {
     let cli = Cli::parse();
+    if let Command::Check { path } = &cli.command {
+        return check(path);
+    }
+
     let monitor = cli.monitor.unwrap_or(String::from("NOMONITOR"));
     let mut conn =
         UnixStream::connect("/run/user/1000/lxwengd.sock").expect("Unable to connect to LxWEngd");
 
+    // Logs may keep coming, so they are printed as they arrive
+    let streaming = matches!(cli.command, Command::Logs { .. });
     let msg = match cli.command {
         Command::Playlist {
-            paused,
+            paused: _,
             resume,
+            shuffle,
+            strict,
             path,
         } => format!(
-            "load {} {} {} {}\n",
+            "load {} {} {} {} {}\n",
             path.to_string_lossy(),
             monitor,
-            paused,
-            resume
+            matches!(resume, ResumeMode::True),
+            shuffle,
+            strict
         ),
         Command::Stop { no_resume } => format!("unload {no_resume} {monitor}\n"),
 
         Command::Play => format!("play {monitor}\n"),
-        Command::Pause { clear } => format!("pause {clear} {monitor}\n"),
-        Command::Toggle { clear } => format!("toggle {clear} {monitor}\n"),
+        Command::Pause { clear, freeze } => {
+            format!("pause {} {monitor}\n", pause_mode(clear, freeze))
+        }
+        Command::Toggle { clear, freeze } => {
+            format!("toggle {} {monitor}\n", pause_mode(clear, freeze))
+        }
 
         Command::Status => String::from("status\n"),
+        Command::Logs { follow } => format!("logs {follow} {monitor}\n"),
         Command::Quit => String::from("quit\n"),
+        Command::Check { .. } => unreachable!(),
     };
     conn.write_all(msg.as_bytes()).unwrap();
 
+    if streaming {
+        let _ = std::io::copy(&mut conn, &mut std::io::stdout());
+        return ExitCode::SUCCESS;
+    }
     let mut response = String::new();
     conn.read_to_string(&mut response).unwrap();
     println!("{response}");
+    ExitCode::SUCCESS
 }
@@ -2,25 +2,62 @@
 
 use smol::process::{Command, Stdio};
 use std::collections::HashMap;
+use std::env;
+use std::ffi::OsStr;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
 
 use crate::daemon::{CACHE_PATH, CFG};
+use crate::runner::RunnerError;
+use crate::utils::output::EngineLog;
+
+/// Properties recognised by [`Backend`], others are passed to the wallpaper with
+/// `--set-property`.
+pub const PROPERTIES: &[&str] = &[
+    "silent",
+    "audio",
+    "automute",
+    "fullscreen-pause",
+    "mouse",
+    "fps",
+    "volume",
+    "window",
+    "scaling",
+    "clamp",
+];
 
 pub struct Backend {
     monitor: Option<String>,
     default_props: HashMap<String, String>,
+    /// Where the output of `linux-wallpaperengine` goes.
+    log: EngineLog,
 }
 
 impl Backend {
-    pub fn new(monitor: Option<String>) -> Self {
+    pub fn new(monitor: Option<String>, log: EngineLog) -> Self {
         Self {
             monitor,
             default_props: HashMap::new(),
+            log,
         }
     }
 
+    /// Returns the [`EngineLog`] that children should be attached to.
+    pub fn log(&self) -> EngineLog {
+        self.log.clone()
+    }
+
     /// Gets the [`Command`] to start `linux-wallpaperengine`.
-    pub fn get_sys_command(&self, name: &str, properties: &HashMap<String, String>) -> Command {
-        let mut sys_cmd = Command::new(CFG.binary.as_deref().unwrap_or("linux-wallpaperengine"));
+    pub fn get_sys_command(
+        &self,
+        name: &str,
+        properties: &HashMap<String, Option<String>>,
+    ) -> Command {
+        // Spawned in its own process group, so that it can be frozen as a whole
+        let mut sys_cmd =
+            std::process::Command::new(CFG.binary.as_deref().unwrap_or("linux-wallpaperengine"));
+        sys_cmd.process_group(0);
+        let mut sys_cmd = Command::from(sys_cmd);
         if let Some(value) = &CFG.assets_path {
             sys_cmd.arg("--assets-dir").arg(value);
         }
@@ -33,8 +70,8 @@ impl Backend {
         }
         sys_cmd.arg(name);
         sys_cmd
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
             .current_dir(CACHE_PATH.to_path_buf());
         sys_cmd
     }
@@ -45,15 +82,44 @@ impl Backend {
     }
 }
 
-/// Combine 2 [`HashMap`]s.
-fn combine<'a>(
+/// Checks that the `linux-wallpaperengine` binary can be found, so that a load can tell
+/// before anything is spawned.
+///
+/// # Errors
+/// If the binary is not found, returns [`RunnerError::CannotSpawn`].
+pub fn check_binary() -> Result<(), RunnerError> {
+    let binary = CFG.binary.as_deref().unwrap_or("linux-wallpaperengine");
+    if locate(binary, env::var_os("PATH").as_deref()) {
+        Ok(())
+    } else {
+        Err(RunnerError::CannotSpawn(format!("`{binary}` is not found")))
+    }
+}
+
+/// Whether `binary` is a file, or is found in `paths` if it's just a name.
+fn locate(binary: &str, paths: Option<&OsStr>) -> bool {
+    if binary.contains('/') {
+        return Path::new(binary).is_file();
+    }
+    paths.is_some_and(|paths| env::split_paths(paths).any(|dir| dir.join(binary).is_file()))
+}
+
+/// Combine 2 [`HashMap`]s, an override of [`None`] removes the property.
+pub fn combine<'a>(
     base: &'a HashMap<String, String>,
-    overrides: &'a HashMap<String, String>,
+    overrides: &'a HashMap<String, Option<String>>,
 ) -> HashMap<String, String> {
     let mut result = base.to_owned();
     for (key, value) in overrides {
-        let entry = result.entry(key.to_string()).or_default();
-        *entry = value.to_string();
+        match value {
+            Some(value) => {
+                let entry = result.entry(key.to_string()).or_default();
+                *entry = value.to_string();
+            }
+            None => {
+                result.remove(key);
+            }
+        }
     }
     result
 }
@@ -108,15 +174,6 @@ fn map_properties(properties: &HashMap<String, String>, cmd: &mut Command) {
     }
 }
 
-/// Output properties in form or key-value pairs for dry-run
-pub fn pretty_print(properties: &HashMap<String, String>) -> String {
-    let mut result = String::new();
-    for (key, value) in properties {
-        result.push_str(&format!("{key}={value} "));
-    }
-    result
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -129,9 +186,9 @@ mod tests {
         base.insert(String::from("xixi"), String::from("noxixi"));
 
         let mut overrides = HashMap::new();
-        overrides.insert(String::from("unknown"), String::from("got it!"));
-        overrides.insert(String::from("known"), String::from("umm"));
-        overrides.insert(String::from("woo"), String::from("hoo"));
+        overrides.insert(String::from("unknown"), Some(String::from("got it!")));
+        overrides.insert(String::from("known"), Some(String::from("umm")));
+        overrides.insert(String::from("woo"), Some(String::from("hoo")));
 
         let mut expected = HashMap::new();
         expected.insert(String::from("unknown"), String::from("got it!"));
@@ -140,5 +197,19 @@ mod tests {
         expected.insert(String::from("woo"), String::from("hoo"));
 
         assert_eq!(combine(&base, &overrides), expected);
+
+        overrides.insert(String::from("xixi"), None);
+        overrides.insert(String::from("nothing"), None);
+        expected.remove("xixi");
+        assert_eq!(combine(&base, &overrides), expected);
+    }
+
+    #[test]
+    fn locate_binary() {
+        assert!(locate("sh", Some(OsStr::new("/nonexistent:/bin"))));
+        assert!(!locate("sh", Some(OsStr::new("/nonexistent"))));
+        assert!(!locate("sh", None));
+        assert!(locate("/bin/sh", None));
+        assert!(!locate("/nonexistent/sh", Some(OsStr::new("/bin"))));
     }
 }
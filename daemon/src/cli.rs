@@ -41,6 +41,29 @@ struct Cli {
     )]
     assets_path: Option<PathBuf>,
 
+    #[arg(
+        short = 'w',
+        long = "workshop-path",
+        value_name = "PATH",
+        help = "Path to the Steam workshop folder of Wallpaper Engine."
+    )]
+    workshop_path: Option<PathBuf>,
+
+    #[arg(
+        short = 'r',
+        long = "restarts",
+        value_name = "N",
+        default_value_t = 3,
+        help = "Times to restart a crashed linux-wallpaperengine before skipping the wallpaper."
+    )]
+    restarts: u32,
+
+    #[arg(
+        long = "mirror-logs",
+        help = "Also write the output of linux-wallpaperengine to <monitor>.log in the cache directory."
+    )]
+    mirror_logs: bool,
+
     #[arg(
         long = "standby",
         help = "Do not load the default playlist on startup."
@@ -52,7 +75,10 @@ pub struct Config {
     pub default_playlist: PathBuf,
     pub default_monitor: Option<String>,
     pub assets_path: Option<PathBuf>,
+    pub workshop_path: Option<PathBuf>,
     pub binary: Option<String>,
+    pub restarts: u32,
+    pub mirror_logs: bool,
     pub standby: bool,
 }
 
@@ -68,7 +94,10 @@ pub fn configure() -> Config {
         default_playlist,
         default_monitor: parsed.monitor,
         assets_path: parsed.assets_path,
+        workshop_path: parsed.workshop_path,
         binary: parsed.binary,
+        restarts: parsed.restarts,
+        mirror_logs: parsed.mirror_logs,
         standby: parsed.standby,
     }
 }
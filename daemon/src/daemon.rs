@@ -4,7 +4,7 @@
 //! Unless `--standby` is passed in the arguments, the programs attempts to find the default
 //! playlist and runs it on all possible monitors.
 
-use smol::lock::Mutex;
+use smol::lock::{Mutex, RwLock};
 use std::collections::HashMap;
 use std::env;
 use std::io::{BufRead, BufReader, Write};
@@ -15,11 +15,14 @@ use std::str::FromStr;
 use std::sync::{Arc, LazyLock};
 use thiserror::Error;
 
+use crate::backend;
 use crate::cli::{Config, configure};
 use crate::runner::NOMONITOR_INDICATOR;
 use crate::runner::{Action, Runner, RunnerHandle};
 use crate::utils::ipc::IPCCmd;
-use crate::utils::state::load_state;
+use crate::utils::playlist;
+use crate::utils::state::{SavedState, load_state};
+use crate::utils::workshop::{self, Library};
 
 pub static CFG: LazyLock<Config> = LazyLock::new(configure);
 pub static SEARCH_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
@@ -30,6 +33,9 @@ pub static SEARCH_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
     })
 });
 pub static CACHE_PATH: LazyLock<PathBuf> = LazyLock::new(find_cache_path);
+/// Installed workshop wallpapers, indexed on startup.
+/// When a playlist is loaded, they are indexed again only if the library folder has changed since.
+pub static WORKSHOP: LazyLock<RwLock<Library>> = LazyLock::new(RwLock::default);
 
 pub struct LxWEngd {
     runners: HashMap<String, Arc<Mutex<RunnerHandle>>>,
@@ -82,6 +88,34 @@ fn find_search_path() -> Option<PathBuf> {
     Some(default)
 }
 
+fn find_workshop_path() -> Option<PathBuf> {
+    env::var("HOME")
+        .ok()
+        .map(|value| PathBuf::from(value).join(workshop::DEFAULT_LIBRARY))
+}
+
+/// Indexes the Steam workshop into [`WORKSHOP`], unless it has not changed since indexed.
+fn index_workshop() {
+    let Some(path) = CFG.workshop_path.clone().or_else(find_workshop_path) else {
+        log::warn!("Cannot find Steam workshop");
+        return;
+    };
+    if !WORKSHOP.read_blocking().is_stale(&path) {
+        return;
+    }
+    match Library::index(&path) {
+        Ok(library) => {
+            log::info!(
+                "Indexed {} wallpapers in `{}`",
+                library.len(),
+                path.to_string_lossy()
+            );
+            *WORKSHOP.write_blocking() = library;
+        }
+        Err(err) => log::warn!("Cannot index `{}`: {err}", path.to_string_lossy()),
+    }
+}
+
 fn setup_logger() -> Result<(), fern::InitError> {
     fern::Dispatch::new()
         .format(|out, message, record| {
@@ -113,6 +147,7 @@ impl LxWEngd {
                 DaemonError::InitCache
             })?;
         }
+        index_workshop();
 
         Ok(Self {
             runners: HashMap::new(),
@@ -124,22 +159,22 @@ impl LxWEngd {
     ///
     /// # Errors
     /// Fatal errors that will cause the program to exit will be returned here.
-    #[allow(clippy::map_entry)]
     pub fn start(&mut self) {
         if !CFG.standby {
             let monitor = CFG
                 .default_monitor
                 .clone()
                 .unwrap_or(NOMONITOR_INDICATOR.to_string());
-            let index = if let Ok(index) = load_state(&CFG.default_playlist) {
-                index
+            let state = if let Ok(state) = load_state(&CFG.default_playlist) {
+                state
             } else {
                 log::error!("Failed to resume state");
-                0
+                SavedState::default()
             };
-            match Runner::from_index(monitor.clone(), CFG.default_playlist.clone(), index) {
+            match Runner::from_state(monitor, CFG.default_playlist.clone(), state, false) {
                 Ok((mut runner, handle)) => {
                     // One runner runs on one monitor
+                    let monitor = handle.lock_blocking().monitor().to_string();
                     self.runners.insert(monitor, handle);
                     smol::spawn(async move {
                         runner.run().await;
@@ -161,39 +196,12 @@ impl LxWEngd {
                         path,
                         monitor,
                         resume,
+                        shuffle,
+                        strict,
                     }) => {
                         Self::try_cleanup(&mut self.runners);
-                        if self.runners.contains_key(&monitor) {
-                            let err = format!("Already have a runner on {monitor}");
-                            log::error!("{err}");
-                            let _ = conn.write_all(&err.into_bytes());
-                        } else {
-                            let index = if resume {
-                                if let Ok(index) = load_state(&path) {
-                                    index
-                                } else {
-                                    log::error!("Failed to resume state");
-                                    0
-                                }
-                            } else {
-                                0
-                            };
-                            match Runner::from_index(monitor.clone(), path, index) {
-                                Ok((mut runner, handle)) => {
-                                    // One runner runs on one monitor
-                                    self.runners.insert(monitor, handle);
-                                    smol::spawn(async move {
-                                        runner.run().await;
-                                    })
-                                    .detach();
-                                    let _ = conn.write_all(b"OK");
-                                }
-                                Err(err) => {
-                                    log::error!("{err}");
-                                    let _ = conn.write_all(&err.to_string().into_bytes());
-                                }
-                            }
-                        }
+                        let reply = self.load(path, monitor, resume, shuffle, strict);
+                        let _ = conn.write_all(&reply.into_bytes());
                     }
 
                     Ok(IPCCmd::Unload { no_save, monitor }) => {
@@ -214,9 +222,9 @@ impl LxWEngd {
                         }
                     }
 
-                    Ok(IPCCmd::Pause { clear, monitor }) => {
+                    Ok(IPCCmd::Pause { mode, monitor }) => {
                         Self::try_cleanup(&mut self.runners);
-                        if self.forward_action(&monitor, Action::Pause(clear)).is_ok() {
+                        if self.forward_action(&monitor, Action::Pause(mode)).is_ok() {
                             let _ = conn.write_all(b"OK");
                         } else {
                             let _ = conn.write_all(b"No such runner");
@@ -225,13 +233,37 @@ impl LxWEngd {
 
                     Ok(IPCCmd::Play { monitor }) => {
                         Self::try_cleanup(&mut self.runners);
-                        if self.forward_action(&monitor, Action::Next).is_ok() {
+                        if self.forward_action(&monitor, Action::Resume).is_ok() {
                             let _ = conn.write_all(b"OK");
                         } else {
                             let _ = conn.write_all(b"No such runner");
                         }
                     }
 
+                    Ok(IPCCmd::Logs { follow, monitor }) => {
+                        Self::try_cleanup(&mut self.runners);
+                        if let Some(lock) = self.runners.get(&monitor) {
+                            let logs = lock.lock_blocking().logs();
+                            if follow {
+                                // Keep sending new output without blocking other clients
+                                let (lines, rx) = logs.follow();
+                                std::thread::spawn(move || {
+                                    let _ = conn.write_all(join_lines(&lines).as_bytes());
+                                    while let Ok(line) = rx.recv_blocking() {
+                                        if conn.write_all(format!("{line}\n").as_bytes()).is_err() {
+                                            break;
+                                        }
+                                    }
+                                    let _ = conn.shutdown(Shutdown::Both);
+                                });
+                                continue;
+                            }
+                            let _ = conn.write_all(join_lines(&logs.lines()).as_bytes());
+                        } else {
+                            let _ = conn.write_all(b"No such runner");
+                        }
+                    }
+
                     Ok(IPCCmd::Status) => {
                         Self::try_cleanup(&mut self.runners);
                         let status = self.status_string();
@@ -247,8 +279,22 @@ impl LxWEngd {
                         break;
                     }
 
-                    Ok(IPCCmd::Toggle { monitor: _ }) => {
-                        todo!()
+                    Ok(IPCCmd::Toggle { mode, monitor }) => {
+                        Self::try_cleanup(&mut self.runners);
+                        let paused = self
+                            .runners
+                            .get(&monitor)
+                            .is_some_and(|lock| lock.lock_blocking().paused());
+                        let action = if paused {
+                            Action::Resume
+                        } else {
+                            Action::Pause(mode)
+                        };
+                        if self.forward_action(&monitor, action).is_ok() {
+                            let _ = conn.write_all(b"OK");
+                        } else {
+                            let _ = conn.write_all(b"No such runner");
+                        }
                     }
 
                     Err(err) => {
@@ -263,6 +309,74 @@ impl LxWEngd {
         }
     }
 
+    /// Loads a playlist into a new [`Runner`].
+    ///
+    /// Returns the reply to the client, which tells how the playlist is parsed.
+    #[allow(clippy::map_entry)]
+    fn load(
+        &mut self,
+        path: PathBuf,
+        monitor: String,
+        resume: bool,
+        shuffle: bool,
+        strict: bool,
+    ) -> String {
+        index_workshop();
+        let mut parsed = match playlist::open(&path) {
+            Ok(file) => playlist::check(&path, &file),
+            Err(err) => {
+                log::error!("{err}");
+                return format!("{}: {err}", path.to_string_lossy());
+            }
+        };
+        parsed.check_installed(&WORKSHOP.read_blocking());
+        let summary = parsed.summary();
+        if strict && parsed.has_errors() {
+            return format!("Refusing to load a playlist with errors\n{summary}");
+        }
+
+        let state = if resume {
+            if let Ok(state) = load_state(&path) {
+                state
+            } else {
+                log::error!("Failed to resume state");
+                SavedState::default()
+            }
+        } else {
+            SavedState::default()
+        };
+        // The playlist may declare its monitor, so check for existing runners
+        // only after it's parsed
+        match Runner::from_playlist(monitor, path, parsed, state, shuffle) {
+            Ok((mut runner, handle)) => {
+                let monitor = handle.lock_blocking().monitor().to_string();
+                if self.runners.contains_key(&monitor) {
+                    let err = format!("Already have a runner on {monitor}");
+                    log::error!("{err}");
+                    err
+                } else {
+                    // One runner runs on one monitor
+                    self.runners.insert(monitor, handle);
+                    smol::spawn(async move {
+                        runner.run().await;
+                    })
+                    .detach();
+                    match backend::check_binary() {
+                        Ok(()) => format!("OK\n{summary}"),
+                        Err(err) => {
+                            log::error!("{err}");
+                            format!("OK\n{summary}\nWarning: {err}, wallpapers cannot be shown")
+                        }
+                    }
+                }
+            }
+            Err(err) => {
+                log::error!("{err}");
+                format!("{err}\n{summary}")
+            }
+        }
+    }
+
     /// When [`Runner`]s exit, they set their state to [`State::Exited`].
     /// However, they are not automatically deregistered.
     ///
@@ -271,7 +385,11 @@ impl LxWEngd {
     fn try_cleanup(runners: &mut HashMap<String, Arc<Mutex<RunnerHandle>>>) {
         let mut result = HashMap::new();
         for (id, runner) in runners.drain() {
-            if !runner.lock_blocking().exited() {
+            let handle = runner.lock_blocking();
+            if handle.exited() {
+                log::info!("Runner {id} is removed: {}", handle.status());
+            } else {
+                drop(handle);
                 result.insert(id, runner);
             }
         }
@@ -300,6 +418,11 @@ impl LxWEngd {
     }
 }
 
+/// Joins lines of output, each ending with a newline.
+fn join_lines(lines: &[String]) -> String {
+    lines.iter().map(|line| format!("{line}\n")).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -317,8 +440,13 @@ mod tests {
                 find_search_path().unwrap(),
                 PathBuf::from("./.config/lxwengd")
             );
+            assert_eq!(
+                find_workshop_path().unwrap(),
+                PathBuf::from("./.steam/steam/steamapps/workshop/content/431960")
+            );
             env::remove_var("HOME");
             assert!(find_search_path().is_none());
+            assert!(find_workshop_path().is_none());
 
             env::set_var("XDG_CACHE_HOME", "/some_cachey_place");
             assert_eq!(
@@ -5,3 +5,6 @@ mod runner;
 mod utils;
 
 pub use daemon::{DaemonError, LxWEngd};
+pub use runner::{RunnerError, dry_run};
+pub use utils::playlist;
+pub use utils::{Diagnostic, ParseError};
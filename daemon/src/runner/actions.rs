@@ -1,11 +1,14 @@
 //! An action can be requested for the [`Runner`] to perform.
 //!
 //! These actions will interrupt the the current task.
-//! When the runner is in paused state,
-//! any of these actions will put it back to normal mode.
+//! When the runner is in paused state, [`Action::Resume`] resumes the task where it was paused,
+//! and other actions are performed as usual.
+
+use std::str::FromStr;
 
 use crate::runner::Command;
 
+#[allow(dead_code)]
 pub enum Action {
     /// Jump to next [`Command`].
     Next,
@@ -17,9 +20,36 @@ pub enum Action {
     /// Execute a [`Command`] specified by the user manually.
     Exec(Command),
 
-    /// Pause current [`Command`]. bool indicates whether to terminate the child.
-    Pause(bool),
+    /// Pause current [`Command`], [`PauseMode`] tells what to do with the child.
+    Pause(PauseMode),
+    /// Resume the paused [`Command`] for the rest of its duration, or continue after `stop`.
+    Resume,
 
     /// Terminates the [`Runner`] because of user request.
     Exit,
 }
+
+/// What to do with `linux-wallpaperengine` when pausing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PauseMode {
+    /// Keep it running, only the timer is paused.
+    Keep,
+    /// Terminate it, it's spawned again on resume.
+    Clear,
+    /// Stop its process group with SIGSTOP, so the frame stays on screen without using CPU.
+    /// It's continued with SIGCONT on resume.
+    Freeze,
+}
+
+impl FromStr for PauseMode {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "keep" => Ok(Self::Keep),
+            "clear" => Ok(Self::Clear),
+            "freeze" => Ok(Self::Freeze),
+            _ => Err(()),
+        }
+    }
+}
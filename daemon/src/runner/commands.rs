@@ -2,79 +2,504 @@
 //!
 //! This module do the parsing job.
 
+use chrono::{Datelike, NaiveDate, NaiveTime, Weekday};
 use nom::branch::alt;
-use nom::bytes::complete::{tag, take_till1};
-use nom::character::complete::{alphanumeric1, char, space0};
-use nom::combinator::{map, map_res, opt, rest};
-use nom::multi::separated_list0;
-use nom::sequence::{pair, separated_pair};
-use nom::{Finish, IResult, Parser};
+use nom::bytes::complete::{tag, take_till1, take_while1};
+use nom::character::complete::{alphanumeric1, char, digit1, space0, space1};
+use nom::combinator::{cut, eof, map, map_res, opt, peek, rest};
+use nom::error::{ContextError, ErrorKind, FromExternalError, context};
+use nom::multi::{separated_list0, separated_list1};
+use nom::sequence::{pair, preceded, separated_pair, terminated};
+use nom::{Finish, Parser};
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::time::Duration;
 
-use crate::utils::ParseError;
+use crate::utils::workshop::Project;
+use crate::utils::{Diagnostic, ParseError};
+
+/// Error of the parsers in this module, which remembers what is expected where it fails.
+#[derive(Debug, PartialEq)]
+struct Error<I> {
+    input: I,
+    kind: ErrorKind,
+    expected: Option<&'static str>,
+}
+
+impl<I> nom::error::ParseError<I> for Error<I> {
+    fn from_error_kind(input: I, kind: ErrorKind) -> Self {
+        Self {
+            input,
+            kind,
+            expected: None,
+        }
+    }
+
+    fn append(_: I, _: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<I> ContextError<I> for Error<I> {
+    fn add_context(_: I, expected: &'static str, mut other: Self) -> Self {
+        // The innermost context is the most precise one
+        other.expected.get_or_insert(expected);
+        other
+    }
+}
+
+impl<I, E> FromExternalError<I, E> for Error<I> {
+    fn from_external_error(input: I, kind: ErrorKind, _: E) -> Self {
+        nom::error::ParseError::from_error_kind(input, kind)
+    }
+}
+
+type IResult<I, O> = nom::IResult<I, O, Error<I>>;
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Command {
     /// Displays the wallpaper with given id for given duration.
     /// Third argument indicates whether this wallpaper will be displayed forever.
-    /// Last arguments are a list of key-value pairs for recognised properties.
-    Wallpaper(String, CmdDuration, HashMap<String, String>),
+    /// Last arguments are a list of key-value pairs for recognised properties,
+    /// [`None`] drops the default property for this wallpaper only.
+    Wallpaper(String, CmdDuration, HashMap<String, Option<String>>),
     /// Sleeps for given duration.
     Sleep(CmdDuration),
     /// Ends the playlist.
     End,
-    /// Sets default properties for all wallpapers.
+    /// Goes back to the beginning of the playlist.
+    /// The argument limits how many times this is done before falling through, like
+    /// [`Command::Goto`].
+    Loop(Option<usize>),
+    /// Keeps showing the last wallpaper and waits until told to play again.
+    Stop,
+    /// Replaces the loaded playlist with another one, which runs on the same monitor.
+    Then(PathBuf),
+    /// Unloads the runner.
+    Unload,
+    /// Sets default properties for all wallpapers, replacing previous ones.
     Default(HashMap<String, String>),
+    /// Changes some default properties and keeps others, [`None`] removes the property.
+    UpdateDefault(HashMap<String, Option<String>>),
+    /// Jumps to another command.
+    /// The second argument limits how many times the jump is taken before falling through,
+    /// [`None`] means always jump.
+    Goto(Target, Option<usize>),
+    /// Marks a position that `goto` can jump to, does nothing when executed.
+    Label(String),
+    /// Inlines another playlist.
+    /// This is replaced with the content of that playlist by [`crate::utils::playlist::parse`].
+    Include(PathBuf),
+    /// Runs another playlist, then returns to the next command.
+    Call(PathBuf),
+    /// Declares the monitor the playlist is meant for.
+    /// This header directive is taken out by [`crate::utils::playlist::parse`].
+    Monitor(String),
+    /// Declares that wallpapers in the playlist are played in random order.
+    /// This header directive is taken out by [`crate::utils::playlist::parse`].
+    Shuffle,
+    /// Runs some entries picked at random each time it's reached, then continues with the next
+    /// command.
+    /// The first argument is how many entries to pick, each entry comes with its weight.
+    Choose(usize, Vec<(u32, Command)>),
+    /// Runs the commands only if reached between the start and end time, skips them otherwise.
+    /// Once the end time is reached, the rest of the commands are skipped.
+    Between(NaiveTime, NaiveTime, Vec<Command>),
+    /// Runs the commands only if all conditions hold when reached, skips them otherwise.
+    When(Vec<Condition>, Vec<Command>),
+    /// Runs the commands, then restores the default properties from before them.
+    Scope(Vec<Command>),
+    /// Plays every wallpaper in subfolders of the directory that have a `project.json`, each for
+    /// the given duration and with the given properties.
+    /// The directory is scanned each time this is reached.
+    Dir(PathBuf, CmdDuration, Order, HashMap<String, String>),
+    /// Sets a variable, which is expanded as `${name}` in later lines.
+    /// This is taken out by [`crate::utils::playlist::parse`].
+    Set(String, String),
+    /// Displays a wallpaper picked at random among installed ones matching all criteria, for
+    /// given duration and with given properties.
+    /// Another one is picked each time this is reached.
+    Query(Vec<Criterion>, CmdDuration, HashMap<String, String>),
+}
+
+impl Command {
+    /// Whether this is a header directive, which is only allowed at the beginning of a playlist.
+    pub fn is_header(&self) -> bool {
+        matches!(self, Command::Monitor(_) | Command::Shuffle)
+    }
+}
+
+/// A block, whose entries are written on the following lines until a closing `}`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Block {
+    /// `random {` or `choose <n> of {`, which becomes [`Command::Choose`].
+    Choose(usize),
+    /// `between <HH:MM>-<HH:MM> {`, which becomes [`Command::Between`].
+    Between(NaiveTime, NaiveTime),
+    /// `when <condition> ... {`, which becomes [`Command::When`].
+    When(Vec<Condition>),
+    /// A bare `{`, which becomes [`Command::Scope`].
+    Scope,
+}
+
+impl Block {
+    /// Whether entries of this block may have a weight.
+    pub fn weighted(&self) -> bool {
+        matches!(self, Block::Choose(_))
+    }
+
+    /// Builds the [`Command`] from entries of this block and their weights.
+    pub fn build(self, entries: Vec<(u32, Command)>) -> Command {
+        let commands = || entries.iter().map(|(_, cmd)| cmd.clone()).collect();
+        match self {
+            Block::Between(start, end) => Command::Between(start, end, commands()),
+            Block::When(conditions) => Command::When(conditions, commands()),
+            Block::Scope => Command::Scope(commands()),
+            Block::Choose(count) => Command::Choose(count, entries),
+        }
+    }
+}
+
+/// A line in a playlist.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Line {
+    Command(Command),
+    /// Opens a [`Block`].
+    Open(Block),
+    /// Closes the innermost [`Block`].
+    Close,
+}
+
+/// In which order wallpapers of a `dir` are played, given as its `order` property.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Order {
+    /// By the name of the subfolder.
+    #[default]
+    Name,
+    /// By the modification time of the subfolder, oldest first.
+    Mtime,
+    /// In random order, which changes each time.
+    Random,
+}
+
+impl FromStr for Order {
+    type Err = ParseError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "name" => Ok(Order::Name),
+            "mtime" => Ok(Order::Mtime),
+            "random" => Ok(Order::Random),
+            _ => Err(ParseError::InvalidArgument),
+        }
+    }
+}
+
+/// A condition of a `when` block.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    /// `weekday=<day>,<day>...`, holds on any of the given days.
+    Weekday(Vec<Weekday>),
+    /// `date=<MM-DD>..<MM-DD>`, holds from the first date to the second one, both included.
+    /// The range may cross the new year. A single date is written as `date=<MM-DD>`.
+    Date((u32, u32), (u32, u32)),
+}
+
+impl Condition {
+    /// Whether this condition holds on the given date.
+    pub fn holds(&self, date: NaiveDate) -> bool {
+        match self {
+            Condition::Weekday(days) => days.contains(&date.weekday()),
+            Condition::Date(start, end) => {
+                let today = (date.month(), date.day());
+                if start <= end {
+                    *start <= today && today <= *end
+                } else {
+                    *start <= today || today <= *end
+                }
+            }
+        }
+    }
+}
+
+/// A criterion of a `query`, compared against the `project.json` of installed wallpapers.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Criterion {
+    /// `tag=<tag>`, matches wallpapers with the tag.
+    Tag(String),
+    /// `type=<type>`, matches wallpapers of the type, such as `scene`, `video` or `web`.
+    Type(String),
+    /// `title=<text>`, matches wallpapers whose title contains the text.
+    Title(String),
+}
+
+impl Criterion {
+    /// Whether the wallpaper matches this criterion, ignoring case.
+    pub fn matches(&self, project: &Project) -> bool {
+        match self {
+            Criterion::Tag(tag) => project.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)),
+            Criterion::Type(kind) => project.kind.eq_ignore_ascii_case(kind),
+            Criterion::Title(text) => project.title.to_lowercase().contains(&text.to_lowercase()),
+        }
+    }
+}
+
+/// Where a `goto` jumps to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    /// 1-based line number written in the playlist.
+    Line(usize),
+    /// Name of a `label`.
+    Label(String),
+    /// Index into the command list.
+    /// Other targets are resolved to this by [`crate::utils::playlist::parse`].
+    Index(usize),
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum CmdDuration {
     Finite(Duration),
     Infinite,
+    /// Lasts until the clock shows this time, written as `until <HH:MM>`.
+    Until(NaiveTime),
+    /// Lasts for a random duration between the two, both included, picked each time it starts.
+    /// Written as `<min>..<max>`, or `<duration>~<jitter>` for the duration plus or minus jitter.
+    Range(Duration, Duration),
+    /// Lasts until the next multiple of this period on the clock, counted from midnight.
+    /// Written as `align <period>` or `<period>@aligned`.
+    Aligned(Duration),
+}
+
+/// Parses the period of [`CmdDuration::Aligned`], which must not be zero.
+fn parse_period(value: &str) -> Result<Duration, ParseError> {
+    match duration_str::parse(value) {
+        Ok(period) if period >= Duration::from_secs(1) => Ok(period),
+        _ => Err(ParseError::InvalidArgument),
+    }
 }
 
 impl FromStr for CmdDuration {
     type Err = ParseError;
 
     fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Some(time) = value.strip_prefix("until") {
+            return parse_time_str(time.trim()).map(CmdDuration::Until);
+        }
+        if let Some(period) = value
+            .strip_prefix("align")
+            .or_else(|| value.strip_suffix("@aligned"))
+        {
+            return parse_period(period.trim()).map(CmdDuration::Aligned);
+        }
+        let parse = |s: &str| duration_str::parse(s).map_err(|_| ParseError::InvalidArgument);
+        if let Some((min, max)) = value.split_once("..") {
+            let (min, max) = (parse(min)?, parse(max)?);
+            if min > max {
+                return Err(ParseError::InvalidArgument);
+            }
+            return Ok(CmdDuration::Range(min, max));
+        }
+        if let Some((duration, jitter)) = value.split_once('~') {
+            let (duration, jitter) = (parse(duration)?, parse(jitter)?);
+            // The shortest duration picked must still show the wallpaper
+            if jitter >= duration {
+                return Err(ParseError::InvalidArgument);
+            }
+            return Ok(CmdDuration::Range(duration - jitter, duration + jitter));
+        }
         match value {
             "infinite" => Ok(CmdDuration::Infinite),
-            s => {
-                let duration = duration_str::parse(s).map_err(|_| ParseError::InvalidArgument)?;
-                Ok(CmdDuration::Finite(duration))
-            }
+            s => Ok(CmdDuration::Finite(parse(s)?)),
         }
     }
 }
 
+/// Parses a wall clock time written as `HH:MM` or `HH:MM:SS`.
+fn parse_time_str(value: &str) -> Result<NaiveTime, ParseError> {
+    NaiveTime::parse_from_str(value, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M"))
+        .map_err(|_| ParseError::InvalidArgument)
+}
+
+fn parse_time(input: &str) -> IResult<&str, NaiveTime> {
+    context(
+        "a time, such as `18:00`",
+        map_res(
+            take_while1(|c: char| c.is_ascii_digit() || c == ':'),
+            parse_time_str,
+        ),
+    )
+    .parse(input)
+}
+
 fn parse_comment(input: &str) -> IResult<&str, ()> {
     let (input, _) = pair(char('#'), rest).parse(input)?;
     Ok((input, ()))
 }
 
+/// Parses a keyword, which must be followed by whitespace, a comment or the end of the line,
+/// so that a wallpaper such as `shuffled` is not taken for `shuffle`.
+fn keyword<'a>(
+    word: &'static str,
+) -> impl Parser<&'a str, Output = &'a str, Error = Error<&'a str>> {
+    terminated(tag(word), peek(alt((space1, tag("#"), eof))))
+}
+
+/// Parses a piece of text, such as a wallpaper, a path or a property value.
+///
+/// Unquoted text ends at whitespace or `#`. Parts of it may be quoted with `"` to keep
+/// whitespace and `#`, or with `'` to also keep backslashes as is.
+/// Outside single quotes, `\\`, `\"`, `\'`, `\ `, `\#`, `\n` and `\t` are escape sequences.
+fn parse_text(input: &str) -> IResult<&str, String> {
+    fn fail<'a>(input: &'a str, expected: &'static str) -> nom::Err<Error<&'a str>> {
+        nom::Err::Failure(Error {
+            input,
+            kind: ErrorKind::Escaped,
+            expected: Some(expected),
+        })
+    }
+    let mut result = String::new();
+    let mut quote = None;
+    let mut chars = input.char_indices();
+    let mut end = input.len();
+    while let Some((index, c)) = chars.next() {
+        match (quote, c) {
+            (None, c) if c.is_whitespace() || c == '#' => {
+                end = index;
+                break;
+            }
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (Some('\''), c) => result.push(c),
+            (_, '\\') => match chars.next() {
+                Some((_, 'n')) => result.push('\n'),
+                Some((_, 't')) => result.push('\t'),
+                Some((_, c @ ('\\' | '"' | '\'' | ' ' | '#'))) => result.push(c),
+                _ => return Err(fail(&input[index..], "an escape sequence, such as `\\n`")),
+            },
+            (_, c) => result.push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err(fail(input, "a closing quote"));
+    }
+    if end == 0 {
+        return Err(nom::Err::Error(nom::error::ParseError::from_error_kind(
+            input,
+            ErrorKind::TakeTill1,
+        )));
+    }
+    Ok((&input[end..], result))
+}
+
+/// Parses the name of a property, which may contain `-`, `_` and `.`.
+fn parse_property_key(input: &str) -> IResult<&str, &str> {
+    take_while1(|c: char| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))(input)
+}
+
 fn parse_properties(input: &str) -> IResult<&str, HashMap<String, String>> {
     // `parse_comment` will eat the input if it succeeds
     let (input, _) = opt(parse_comment).parse(input)?;
     let list_parser = separated_list0(
-        space0,
-        separated_pair(alphanumeric1, char('='), alphanumeric1),
+        space1,
+        separated_pair(
+            parse_property_key,
+            char('='),
+            context("a property value", parse_text),
+        ),
     );
-    let mut prop_parser = map(list_parser, |list: Vec<(&str, &str)>| {
+    let mut prop_parser = map(list_parser, |list: Vec<(&str, String)>| {
         list.into_iter()
-            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .map(|(k, v)| (k.to_string(), v))
             .collect::<HashMap<String, String>>()
     });
     prop_parser.parse(input)
 }
 
+/// Parses properties that override the default ones, which are either `key=value`, or `-key` to
+/// drop a default property.
+fn parse_overrides(input: &str) -> IResult<&str, HashMap<String, Option<String>>> {
+    // `parse_comment` will eat the input if it succeeds
+    let (input, _) = opt(parse_comment).parse(input)?;
+    let list_parser = separated_list0(
+        space1,
+        alt((
+            map(preceded(char('-'), parse_property_key), |key| (key, None)),
+            map(
+                separated_pair(
+                    parse_property_key,
+                    char('='),
+                    context("a property value", parse_text),
+                ),
+                |(key, value)| (key, Some(value)),
+            ),
+        )),
+    );
+    map(list_parser, |list: Vec<(&str, Option<String>)>| {
+        list.into_iter().map(|(k, v)| (k.to_string(), v)).collect()
+    })
+    .parse(input)
+}
+
+/// Parses changes of `default`, which are `+key=value` to set a property and `-key` to remove one.
+fn parse_default_changes(input: &str) -> IResult<&str, HashMap<String, Option<String>>> {
+    let change = alt((
+        map(preceded(char('-'), parse_property_key), |key| (key, None)),
+        map(
+            preceded(
+                char('+'),
+                cut(separated_pair(
+                    parse_property_key,
+                    char('='),
+                    context("a property value", parse_text),
+                )),
+            ),
+            |(key, value)| (key, Some(value)),
+        ),
+    ));
+    map(separated_list1(space1, change), |list| {
+        list.into_iter()
+            .map(|(k, v): (&str, Option<String>)| (k.to_string(), v))
+            .collect()
+    })
+    .parse(input)
+}
+
+fn parse_until(input: &str) -> IResult<&str, CmdDuration> {
+    let (input, _) = tag("until")(input)?;
+    let (input, _) = space1(input)?;
+    map(cut(parse_time), CmdDuration::Until).parse(input)
+}
+
+fn parse_align(input: &str) -> IResult<&str, CmdDuration> {
+    let (input, _) = tag("align")(input)?;
+    let (input, _) = space1(input)?;
+    map(
+        cut(context(
+            "a period, such as `15m`",
+            map_res(take_till1(|c: char| c.is_whitespace()), parse_period),
+        )),
+        CmdDuration::Aligned,
+    )
+    .parse(input)
+}
+
 fn parse_duration(input: &str) -> IResult<&str, CmdDuration> {
     // `parse_comment` will eat the input if it succeeds
     let (input, _) = opt(parse_comment).parse(input)?;
-    map_res(
-        take_till1(|c: char| c.is_whitespace()),
-        CmdDuration::from_str,
+    if let Ok(result) = parse_until(input) {
+        return Ok(result);
+    }
+    if let Ok(result) = parse_align(input) {
+        return Ok(result);
+    }
+    context(
+        "a duration, such as `15m`, `infinite` or `until 18:00`",
+        map_res(
+            take_till1(|c: char| c.is_whitespace()),
+            CmdDuration::from_str,
+        ),
     )
     .parse(input)
 }
@@ -83,33 +508,354 @@ fn parse_end(input: &str) -> IResult<&str, Command> {
     map(tag("end"), |_| Command::End).parse(input)
 }
 
+fn parse_loop(input: &str) -> IResult<&str, Command> {
+    let (input, _) = keyword("loop").parse(input)?;
+    let (input, times) = opt(preceded(
+        space1,
+        context(
+            "how many times to loop",
+            map_res(digit1, str::parse::<usize>),
+        ),
+    ))
+    .parse(input)?;
+    Ok((input, Command::Loop(times)))
+}
+
+fn parse_stop(input: &str) -> IResult<&str, Command> {
+    map(keyword("stop"), |_| Command::Stop).parse(input)
+}
+
+fn parse_then(input: &str) -> IResult<&str, Command> {
+    let (input, _) = tag("then")(input)?;
+    let (input, _) = space1(input)?;
+    map(cut(parse_playlist_path), Command::Then).parse(input)
+}
+
+fn parse_unload(input: &str) -> IResult<&str, Command> {
+    map(keyword("unload"), |_| Command::Unload).parse(input)
+}
+
+fn parse_at(input: &str) -> IResult<&str, Command> {
+    let (input, _) = tag("at")(input)?;
+    let (input, _) = space1(input)?;
+    map(cut(parse_time), |time| {
+        Command::Sleep(CmdDuration::Until(time))
+    })
+    .parse(input)
+}
+
 fn parse_sleep(input: &str) -> IResult<&str, Command> {
     let (input, _) = tag("sleep")(input)?;
     let (input, _) = space0(input)?;
     map(parse_duration, Command::Sleep).parse(input)
 }
 
+fn parse_label_name(input: &str) -> IResult<&str, &str> {
+    take_while1(|c: char| c.is_alphanumeric() || c == '_' || c == '-')(input)
+}
+
+fn parse_target(input: &str) -> IResult<&str, Target> {
+    map(
+        context("a label or a line number", parse_label_name),
+        |name: &str| {
+            if let Ok(line) = name.parse::<usize>() {
+                Target::Line(line)
+            } else {
+                Target::Label(name.to_string())
+            }
+        },
+    )
+    .parse(input)
+}
+
+fn parse_label(input: &str) -> IResult<&str, Command> {
+    let (input, _) = tag("label")(input)?;
+    let (input, _) = space1(input)?;
+    cut(context(
+        "a label name, which is not a number",
+        map_res(parse_label_name, |name: &str| {
+            // Plain numbers would be taken as line numbers by `goto`
+            if name.chars().all(|c| c.is_ascii_digit()) {
+                Err(ParseError::InvalidArgument)
+            } else {
+                Ok(Command::Label(name.to_string()))
+            }
+        }),
+    ))
+    .parse(input)
+}
+
+fn parse_goto(input: &str) -> IResult<&str, Command> {
+    let (input, _) = tag("goto")(input)?;
+    let (input, _) = space1(input)?;
+    let (input, target) = cut(parse_target).parse(input)?;
+    let (input, _) = space0(input)?;
+    let (input, times) = opt(map_res(digit1, str::parse::<usize>)).parse(input)?;
+    Ok((input, Command::Goto(target, times)))
+}
+
+fn parse_playlist_path(input: &str) -> IResult<&str, PathBuf> {
+    context("a playlist", map(parse_text, PathBuf::from)).parse(input)
+}
+
+fn parse_include(input: &str) -> IResult<&str, Command> {
+    let (input, _) = tag("include")(input)?;
+    let (input, _) = space1(input)?;
+    map(cut(parse_playlist_path), Command::Include).parse(input)
+}
+
+fn parse_call(input: &str) -> IResult<&str, Command> {
+    let (input, _) = tag("call")(input)?;
+    let (input, _) = space1(input)?;
+    map(cut(parse_playlist_path), Command::Call).parse(input)
+}
+
+fn parse_monitor(input: &str) -> IResult<&str, Command> {
+    let (input, _) = tag("monitor")(input)?;
+    let (input, _) = space1(input)?;
+    map(
+        cut(context(
+            "a monitor name",
+            take_till1(|c: char| c.is_whitespace() || c == '#'),
+        )),
+        |name: &str| Command::Monitor(name.to_string()),
+    )
+    .parse(input)
+}
+
+fn parse_set(input: &str) -> IResult<&str, Command> {
+    let (input, _) = tag("set")(input)?;
+    let (input, _) = space1(input)?;
+    map(
+        cut(context(
+            "a variable, such as `name=value`",
+            separated_pair(parse_label_name, char('='), parse_text),
+        )),
+        |(name, value): (&str, String)| Command::Set(name.to_string(), value),
+    )
+    .parse(input)
+}
+
+fn parse_shuffle(input: &str) -> IResult<&str, Command> {
+    map(keyword("shuffle"), |_| Command::Shuffle).parse(input)
+}
+
 fn parse_default(input: &str) -> IResult<&str, Command> {
     let (input, _) = tag("default")(input)?;
     let (input, _) = space0(input)?;
-    let (input, props) = parse_properties(input)?;
-    Ok((input, Command::Default(props)))
+    alt((
+        map(tag("reset"), |_| Command::Default(HashMap::new())),
+        map(parse_default_changes, Command::UpdateDefault),
+        map(parse_properties, Command::Default),
+    ))
+    .parse(input)
 }
 
 fn parse_wallpaper(input: &str) -> IResult<&str, Command> {
-    let (input, id) = take_till1(|c: char| c.is_whitespace() || c == '#')(input)?;
+    let (input, id) = context("a command or a wallpaper", parse_text).parse(input)?;
     let (input, _) = space0(input)?;
 
     let (input, duration) = parse_duration(input)?;
     let (input, _) = space0(input)?;
 
+    let (input, props) = parse_overrides(input)?;
+
+    Ok((input, Command::Wallpaper(id, duration, props)))
+}
+
+fn parse_dir(input: &str) -> IResult<&str, Command> {
+    let (input, _) = tag("dir")(input)?;
+    let (input, _) = space1(input)?;
+    let (input, path) = cut(context("a directory", parse_text)).parse(input)?;
+    let (input, _) = space0(input)?;
+    let (input, duration) = cut(parse_duration).parse(input)?;
+    let (input, _) = space0(input)?;
+    let (input, (order, props)) = cut(context(
+        "`order=name`, `order=mtime` or `order=random`",
+        map_res(parse_properties, |mut props| {
+            let order = props
+                .remove("order")
+                .map_or(Ok(Order::default()), |order| order.parse())?;
+            Ok::<_, ParseError>((order, props))
+        }),
+    ))
+    .parse(input)?;
+    Ok((
+        input,
+        Command::Dir(PathBuf::from(path), duration, order, props),
+    ))
+}
+
+fn parse_criterion(input: &str) -> IResult<&str, Criterion> {
+    let (input, key) = alt((tag("tag"), tag("type"), tag("title"))).parse(input)?;
+    let (input, _) = char('=')(input)?;
+    let (input, value) = cut(context("a value", parse_text)).parse(input)?;
+    let criterion = match key {
+        "tag" => Criterion::Tag(value),
+        "type" => Criterion::Type(value),
+        _ => Criterion::Title(value),
+    };
+    Ok((input, criterion))
+}
+
+fn parse_query(input: &str) -> IResult<&str, Command> {
+    let (input, _) = tag("query")(input)?;
+    let (input, _) = space1(input)?;
+    let (input, criteria) = cut(context(
+        "a criterion, such as `tag=Nature`, `type=scene` or `title=forest`",
+        separated_list1(space1, parse_criterion),
+    ))
+    .parse(input)?;
+    let (input, _) = space0(input)?;
+    let (input, duration) = cut(parse_duration).parse(input)?;
+    let (input, _) = space0(input)?;
     let (input, props) = parse_properties(input)?;
+    Ok((input, Command::Query(criteria, duration, props)))
+}
+
+fn parse_random(input: &str) -> IResult<&str, Block> {
+    let (input, _) = tag("random")(input)?;
+    let (input, _) = space0(input)?;
+    map(cut(context("`{`", char('{'))), |_| Block::Choose(1)).parse(input)
+}
+
+fn parse_choose(input: &str) -> IResult<&str, Block> {
+    let (input, _) = tag("choose")(input)?;
+    let (input, _) = space1(input)?;
+    let (input, count) = cut(context(
+        "the number of entries to pick",
+        map_res(digit1, str::parse::<usize>),
+    ))
+    .parse(input)?;
+    let (input, _) = space0(input)?;
+    let (input, _) = cut(context("`of`", tag("of"))).parse(input)?;
+    let (input, _) = space0(input)?;
+    map(cut(context("`{`", char('{'))), move |_| {
+        Block::Choose(count)
+    })
+    .parse(input)
+}
 
-    Ok((input, Command::Wallpaper(id.to_string(), duration, props)))
+fn parse_between(input: &str) -> IResult<&str, Block> {
+    let (input, _) = tag("between")(input)?;
+    let (input, _) = space1(input)?;
+    let (input, (start, end)) = cut(context(
+        "a time range, such as `22:00-06:00`",
+        separated_pair(parse_time, char('-'), parse_time),
+    ))
+    .parse(input)?;
+    let (input, _) = space0(input)?;
+    map(cut(context("`{`", char('{'))), move |_| {
+        Block::Between(start, end)
+    })
+    .parse(input)
+}
+
+/// Parses a date in a year written as `MM-DD`.
+fn parse_month_day(input: &str) -> IResult<&str, (u32, u32)> {
+    map_res(
+        separated_pair(digit1, char('-'), digit1),
+        |(month, day): (&str, &str)| {
+            let month = month.parse().map_err(|_| ParseError::InvalidArgument)?;
+            let day = day.parse().map_err(|_| ParseError::InvalidArgument)?;
+            // Any leap year accepts every valid date
+            NaiveDate::from_ymd_opt(2000, month, day)
+                .map(|_| (month, day))
+                .ok_or(ParseError::InvalidArgument)
+        },
+    )
+    .parse(input)
+}
+
+fn parse_weekday_condition(input: &str) -> IResult<&str, Condition> {
+    let (input, _) = tag("weekday=")(input)?;
+    map(
+        cut(context(
+            "weekdays, such as `sat,sun`",
+            separated_list1(char(','), map_res(alphanumeric1, str::parse::<Weekday>)),
+        )),
+        Condition::Weekday,
+    )
+    .parse(input)
+}
+
+fn parse_date_condition(input: &str) -> IResult<&str, Condition> {
+    let (input, _) = tag("date=")(input)?;
+    let date = || context("a date, such as `12-31`", parse_month_day);
+    let (input, start) = cut(date()).parse(input)?;
+    let (input, end) = opt(preceded(tag(".."), cut(date()))).parse(input)?;
+    Ok((input, Condition::Date(start, end.unwrap_or(start))))
+}
+
+fn parse_when(input: &str) -> IResult<&str, Block> {
+    let (input, _) = tag("when")(input)?;
+    let (input, _) = space1(input)?;
+    let (input, conditions) = cut(context(
+        "a condition, such as `weekday=sat,sun` or `date=12-01..12-31`",
+        separated_list1(space1, alt((parse_weekday_condition, parse_date_condition))),
+    ))
+    .parse(input)?;
+    let (input, _) = space0(input)?;
+    map(cut(context("`{`", char('{'))), move |_| {
+        Block::When(conditions.clone())
+    })
+    .parse(input)
+}
+
+fn parse_weight(input: &str) -> IResult<&str, u32> {
+    let (input, weight) = map_res(digit1, str::parse::<u32>).parse(input)?;
+    let (input, _) = char(':')(input)?;
+    let (input, _) = space1(input)?;
+    Ok((input, weight))
+}
+
+fn parse_line(input: &str) -> IResult<&str, Line> {
+    alt((
+        map(
+            alt((
+                parse_random,
+                parse_choose,
+                parse_between,
+                parse_when,
+                map(char('{'), |_| Block::Scope),
+            )),
+            Line::Open,
+        ),
+        map(char('}'), |_| Line::Close),
+        map(parse_command, Line::Command),
+    ))
+    .parse(input)
 }
 
 fn parse_command(input: &str) -> IResult<&str, Command> {
-    alt((parse_end, parse_sleep, parse_default, parse_wallpaper)).parse(input)
+    alt((
+        parse_end,
+        parse_loop,
+        parse_stop,
+        parse_then,
+        parse_unload,
+        parse_sleep,
+        parse_at,
+        parse_goto,
+        parse_label,
+        parse_include,
+        parse_call,
+        parse_monitor,
+        parse_shuffle,
+        parse_set,
+        parse_default,
+        parse_dir,
+        parse_query,
+        parse_wallpaper,
+    ))
+    .parse(input)
+}
+
+/// Checks that nothing but a comment follows a command.
+fn parse_line_end(input: &str) -> IResult<&str, ()> {
+    let (input, _) = space0(input)?;
+    let (input, _) = opt(parse_comment).parse(input)?;
+    map(context("end of line", eof), |_| ()).parse(input)
 }
 
 /// Parse a string.
@@ -124,24 +870,56 @@ fn parse_command(input: &str) -> IResult<&str, Command> {
 /// The parsing might fail because of leading space.
 ///
 /// # Errors
-/// If fails to parse the given string, a [`ParseError`] is returned.
-pub fn parse(input: &str) -> Result<Command, ParseError> {
-    match parse_command(input).finish() {
-        Ok((_, cmd)) => Ok(cmd),
-        Err(nom::error::Error {
-            input: _,
-            code: nom::error::ErrorKind::TakeTill1,
-        }) => Err(ParseError::NotEnoughArguments),
-        Err(nom::error::Error {
-            input: _,
-            code: nom::error::ErrorKind::MapRes,
-        }) => Err(ParseError::InvalidArgument),
-        Err(_) => Err(ParseError::CommandNotFound),
+/// If fails to parse the given string, a [`Diagnostic`] of line 1 is returned.
+pub fn parse(input: &str) -> Result<Command, Diagnostic> {
+    (parse_command, parse_line_end)
+        .parse(input)
+        .finish()
+        .map(|(_, (cmd, ()))| cmd)
+        .map_err(|err| diagnose(input, err))
+}
+
+/// Parse a line of a playlist, which may also open or close a [`Block`].
+///
+/// The line may start with a weight written as `<weight>: `, which is returned separately.
+/// The same restrictions as [`parse`] apply.
+///
+/// # Errors
+/// If fails to parse the given string, a [`Diagnostic`] of line 1 is returned.
+pub fn parse_weighted_line(input: &str) -> Result<(Option<u32>, Line), Diagnostic> {
+    (opt(parse_weight), parse_line, parse_line_end)
+        .parse(input)
+        .finish()
+        .map(|(_, (weight, line, ()))| (weight, line))
+        .map_err(|err| diagnose(input, err))
+}
+
+/// Describes where and why parsing `input` fails.
+fn diagnose(input: &str, err: Error<&str>) -> Diagnostic {
+    let kind = match err.kind {
+        ErrorKind::TakeTill1 => ParseError::NotEnoughArguments,
+        ErrorKind::MapRes | ErrorKind::Digit | ErrorKind::Escaped => ParseError::InvalidArgument,
+        ErrorKind::Eof => ParseError::TrailingInput,
+        _ => ParseError::CommandNotFound,
+    };
+    let offset = input.len() - err.input.len();
+    Diagnostic {
+        kind,
+        line: 1,
+        column: input[..offset].chars().count() + 1,
+        found: err
+            .input
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+        expected: err.expected,
+        source: input.to_string(),
     }
 }
 
 impl FromStr for Command {
-    type Err = ParseError;
+    type Err = Diagnostic;
 
     fn from_str(value: &str) -> Result<Self, Self::Err> {
         parse(value)
@@ -164,6 +942,38 @@ mod tests {
             Ok(("", CmdDuration::Finite(Duration::from_secs(1))))
         );
         assert_eq!(parse_duration("infinite"), Ok(("", CmdDuration::Infinite)));
+        assert_eq!(
+            parse_duration("10m..20m"),
+            Ok((
+                "",
+                CmdDuration::Range(Duration::from_secs(600), Duration::from_secs(1200))
+            ))
+        );
+        assert_eq!(
+            parse_duration("15m~5m"),
+            Ok((
+                "",
+                CmdDuration::Range(Duration::from_secs(600), Duration::from_secs(1200))
+            ))
+        );
+        assert!(parse_duration("1m~5m").is_err());
+        assert!(parse_duration("15m~15m").is_err());
+        assert!(parse_duration("20m..10m").is_err());
+        let quarter = CmdDuration::Aligned(Duration::from_secs(900));
+        assert_eq!(parse_duration("align 15m"), Ok(("", quarter.clone())));
+        assert_eq!(parse_duration("15m@aligned"), Ok(("", quarter)));
+        assert!(parse_duration("align 0s").is_err());
+        assert!(parse_duration("@aligned").is_err());
+        assert!(parse_duration("10m..").is_err());
+        let six = NaiveTime::from_hms_opt(18, 0, 0).unwrap();
+        assert_eq!(
+            parse_duration("until 18:00 # comment"),
+            Ok((" # comment", CmdDuration::Until(six)))
+        );
+        assert_eq!(
+            parse_at("at 18:00:00"),
+            Ok(("", Command::Sleep(CmdDuration::Until(six))))
+        );
 
         assert_eq!(parse_end("end"), Ok(("", Command::End)));
         // assert_eq!(parse_end("nope"), Ok(("", Command::End)));
@@ -171,6 +981,40 @@ mod tests {
             parse_sleep("sleep 1"),
             Ok(("", Command::Sleep(CmdDuration::Finite(Duration::new(1, 0)))))
         );
+        assert_eq!(
+            parse_goto("goto 1 2"),
+            Ok(("", Command::Goto(Target::Line(1), Some(2))))
+        );
+        assert_eq!(
+            parse_goto("goto intro"),
+            Ok(("", Command::Goto(Target::Label("intro".to_string()), None)))
+        );
+        assert_eq!(
+            parse_label("label intro"),
+            Ok(("", Command::Label("intro".to_string())))
+        );
+        assert_eq!(
+            parse_include("include other.playlist # comment"),
+            Ok((
+                " # comment",
+                Command::Include(PathBuf::from("other.playlist"))
+            ))
+        );
+        assert_eq!(
+            parse_call("call other"),
+            Ok(("", Command::Call(PathBuf::from("other"))))
+        );
+        assert_eq!(
+            parse_monitor("monitor eDP-1"),
+            Ok(("", Command::Monitor("eDP-1".to_string())))
+        );
+        assert_eq!(
+            parse_set("set dir=${HOME}/wallpapers # comment"),
+            Ok((
+                " # comment",
+                Command::Set("dir".to_string(), "${HOME}/wallpapers".to_string())
+            ))
+        );
         assert_eq!(
             parse_default("default k1=v1 k2=v2"),
             Ok(("", Command::Default(expected)))
@@ -198,6 +1042,31 @@ mod tests {
         );
         let cmd = "end";
         assert_eq!(parse(cmd), Ok(Command::End));
+        assert_eq!(parse("loop"), Ok(Command::Loop(None)));
+        assert_eq!(parse("loop 3 # times"), Ok(Command::Loop(Some(3))));
+        assert_eq!(parse("stop"), Ok(Command::Stop));
+        assert_eq!(parse("unload"), Ok(Command::Unload));
+        assert_eq!(
+            parse("then 'night.playlist'"),
+            Ok(Command::Then(PathBuf::from("night.playlist")))
+        );
+        assert_eq!(
+            parse("then").map_err(|err| err.kind),
+            Err(ParseError::NotEnoughArguments)
+        );
+        assert_eq!(parse("shuffle # header"), Ok(Command::Shuffle));
+        // Wallpapers may begin with a keyword
+        for id in ["shuffled", "loops", "stopmotion", "unloaded"] {
+            assert_eq!(
+                parse(&format!("{id} 5m")),
+                Ok(Command::Wallpaper(
+                    id.to_string(),
+                    CmdDuration::Finite(Duration::from_secs(5 * 60)),
+                    HashMap::new()
+                ))
+            );
+        }
+        assert_eq!(parse("stop# for good"), Ok(Command::Stop));
         let cmd = "114514 5h";
         assert_eq!(
             parse(cmd),
@@ -209,22 +1078,255 @@ mod tests {
         );
     }
 
+    #[test]
+    fn identify_lines() {
+        assert_eq!(
+            parse_weighted_line("random {"),
+            Ok((None, Line::Open(Block::Choose(1))))
+        );
+        assert_eq!(
+            parse_weighted_line("choose 2 of { # comment"),
+            Ok((None, Line::Open(Block::Choose(2))))
+        );
+        assert_eq!(parse_weighted_line("}"), Ok((None, Line::Close)));
+        assert_eq!(
+            parse_weighted_line("between 22:00-06:00 {"),
+            Ok((
+                None,
+                Line::Open(Block::Between(
+                    NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+                    NaiveTime::from_hms_opt(6, 0, 0).unwrap()
+                ))
+            ))
+        );
+        assert_eq!(
+            parse_weighted_line("between 22:00 {").map_err(|err| err.kind),
+            Err(ParseError::CommandNotFound)
+        );
+        assert_eq!(
+            parse_weighted_line("114514 until 25:00").map_err(|err| err.kind),
+            Err(ParseError::InvalidArgument)
+        );
+        assert_eq!(
+            parse_weighted_line("when weekday=sat,sun date=12-24..01-01 {"),
+            Ok((
+                None,
+                Line::Open(Block::When(vec![
+                    Condition::Weekday(vec![Weekday::Sat, Weekday::Sun]),
+                    Condition::Date((12, 24), (1, 1))
+                ]))
+            ))
+        );
+        assert_eq!(
+            parse_weighted_line("when date=02-30 {").map_err(|err| err.kind),
+            Err(ParseError::InvalidArgument)
+        );
+        assert_eq!(
+            parse_weighted_line("3: sleep 1"),
+            Ok((
+                Some(3),
+                Line::Command(Command::Sleep(CmdDuration::Finite(Duration::new(1, 0))))
+            ))
+        );
+        assert_eq!(
+            parse_weighted_line("114514 5s"),
+            Ok((
+                None,
+                Line::Command(Command::Wallpaper(
+                    "114514".to_string(),
+                    CmdDuration::Finite(Duration::new(5, 0)),
+                    HashMap::new()
+                ))
+            ))
+        );
+        assert_eq!(
+            parse_weighted_line("choose many of {").map_err(|err| err.kind),
+            Err(ParseError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn conditions() {
+        let date = |m, d| NaiveDate::from_ymd_opt(2025, m, d).unwrap();
+        let weekend = Condition::Weekday(vec![Weekday::Sat, Weekday::Sun]);
+        // 2025-12-27 is a Saturday
+        assert!(weekend.holds(date(12, 27)));
+        assert!(!weekend.holds(date(12, 29)));
+
+        let december = Condition::Date((12, 1), (12, 31));
+        assert!(december.holds(date(12, 1)));
+        assert!(december.holds(date(12, 31)));
+        assert!(!december.holds(date(1, 1)));
+
+        let holidays = Condition::Date((12, 24), (1, 1));
+        assert!(holidays.holds(date(12, 25)));
+        assert!(holidays.holds(date(1, 1)));
+        assert!(!holidays.holds(date(1, 2)));
+    }
+
     #[test]
     fn identify_errors() {
         let cmd = "this is a very long string containing nothing but garbage";
-        assert_eq!(parse(cmd), Err(ParseError::InvalidArgument));
+        assert_eq!(
+            parse(cmd).map_err(|err| err.kind),
+            Err(ParseError::InvalidArgument)
+        );
         let cmd = "";
-        assert_eq!(parse(cmd), Err(ParseError::NotEnoughArguments));
+        assert_eq!(
+            parse(cmd).map_err(|err| err.kind),
+            Err(ParseError::NotEnoughArguments)
+        );
         let cmd = "wait    ";
-        assert_eq!(parse(cmd), Err(ParseError::NotEnoughArguments));
+        assert_eq!(
+            parse(cmd).map_err(|err| err.kind),
+            Err(ParseError::NotEnoughArguments)
+        );
+        let cmd = "label 42";
+        assert_eq!(
+            parse(cmd).map_err(|err| err.kind),
+            Err(ParseError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn identify_dir() {
+        let mut props = HashMap::new();
+        props.insert("fps".to_string(), "30".to_string());
+        assert_eq!(
+            parse("dir '/mnt/My Wallpapers' 10m order=mtime fps=30"),
+            Ok(Command::Dir(
+                PathBuf::from("/mnt/My Wallpapers"),
+                CmdDuration::Finite(Duration::from_secs(600)),
+                Order::Mtime,
+                props
+            ))
+        );
+        assert_eq!(
+            parse("dir /mnt 10m"),
+            Ok(Command::Dir(
+                PathBuf::from("/mnt"),
+                CmdDuration::Finite(Duration::from_secs(600)),
+                Order::Name,
+                HashMap::new()
+            ))
+        );
+        assert_eq!(
+            parse("dir /mnt 10m order=size").map_err(|err| err.kind),
+            Err(ParseError::InvalidArgument)
+        );
+        assert_eq!(
+            parse("dir /mnt").map_err(|err| err.kind),
+            Err(ParseError::NotEnoughArguments)
+        );
+    }
+
+    #[test]
+    fn identify_defaults() {
+        assert_eq!(parse("default reset"), Ok(Command::Default(HashMap::new())));
+        assert_eq!(
+            parse("default +fps=30 -volume"),
+            Ok(Command::UpdateDefault(HashMap::from([
+                ("fps".to_string(), Some("30".to_string())),
+                ("volume".to_string(), None)
+            ])))
+        );
+        assert_eq!(
+            parse("default +fps").map_err(|err| err.kind),
+            Err(ParseError::CommandNotFound)
+        );
+        assert_eq!(
+            parse("114514 5m -fps mouse=false"),
+            Ok(Command::Wallpaper(
+                "114514".to_string(),
+                CmdDuration::Finite(Duration::from_secs(300)),
+                HashMap::from([
+                    ("fps".to_string(), None),
+                    ("mouse".to_string(), Some("false".to_string()))
+                ])
+            ))
+        );
+        assert_eq!(
+            parse_weighted_line("{ # keeps defaults"),
+            Ok((None, Line::Open(Block::Scope)))
+        );
+    }
+
+    #[test]
+    fn identify_query() {
+        let mut props = HashMap::new();
+        props.insert("volume".to_string(), "0.5".to_string());
+        assert_eq!(
+            parse("query tag=Nature type=scene 15m volume=0.5"),
+            Ok(Command::Query(
+                vec![
+                    Criterion::Tag("Nature".to_string()),
+                    Criterion::Type("scene".to_string())
+                ],
+                CmdDuration::Finite(Duration::from_secs(900)),
+                props
+            ))
+        );
+        assert_eq!(
+            parse("query title='at night' infinite"),
+            Ok(Command::Query(
+                vec![Criterion::Title("at night".to_string())],
+                CmdDuration::Infinite,
+                HashMap::new()
+            ))
+        );
+        assert_eq!(
+            parse("query 15m").map_err(|err| err.kind),
+            Err(ParseError::CommandNotFound)
+        );
+        assert_eq!(
+            parse("query tag=Nature").map_err(|err| err.kind),
+            Err(ParseError::NotEnoughArguments)
+        );
+
+        let project = Project {
+            title: "City at Night".to_string(),
+            kind: "video".to_string(),
+            tags: vec!["Landscape".to_string()],
+            preview: None,
+        };
+        assert!(Criterion::Tag("landscape".to_string()).matches(&project));
+        assert!(Criterion::Type("Video".to_string()).matches(&project));
+        assert!(Criterion::Title("night".to_string()).matches(&project));
+        assert!(!Criterion::Tag("Nature".to_string()).matches(&project));
+    }
+
+    #[test]
+    fn diagnostics() {
+        let err = parse("114514 5x volume=0.5").unwrap_err();
+        assert_eq!(err.kind, ParseError::InvalidArgument);
+        assert_eq!((err.line, err.column), (1, 8));
+        assert_eq!(err.found, "5x");
+        assert_eq!(
+            err.expected,
+            Some("a duration, such as `15m`, `infinite` or `until 18:00`")
+        );
+
+        let err = parse("114514 5m volume").unwrap_err();
+        assert_eq!(err.kind, ParseError::TrailingInput);
+        assert_eq!((err.column, err.found.as_str()), (11, "volume"));
+        assert_eq!(err.expected, Some("end of line"));
+
+        let err = parse_weighted_line("choose 2 {").unwrap_err();
+        assert_eq!((err.column, err.found.as_str()), (10, "{"));
+        assert_eq!(err.expected, Some("`of`"));
+
+        let err = parse("goto").unwrap_err();
+        assert_eq!(err.kind, ParseError::NotEnoughArguments);
+        assert_eq!((err.column, err.found.as_str()), (5, ""));
+        assert!(parse("end # comment").is_ok());
     }
 
     #[test]
     fn identify_properties() {
         let cmd = "114514 15m dps=15 cup=superbigcup";
         let mut expected = HashMap::new();
-        expected.insert(String::from("dps"), String::from("15"));
-        expected.insert(String::from("cup"), String::from("superbigcup"));
+        expected.insert(String::from("dps"), Some(String::from("15")));
+        expected.insert(String::from("cup"), Some(String::from("superbigcup")));
         assert_eq!(
             parse(cmd),
             Ok(Command::Wallpaper(
@@ -233,9 +1335,40 @@ mod tests {
                 expected
             ))
         );
+        let cmd = r#"'My Wallpapers'/"forest #2" 1s volume=0.5 window=1920x1080+0+0 fullscreen-pause=false title="a \"b\"\tc" path=a\ b/c.d"#;
+        let expected: HashMap<String, Option<String>> = [
+            ("volume", "0.5"),
+            ("window", "1920x1080+0+0"),
+            ("fullscreen-pause", "false"),
+            ("title", "a \"b\"\tc"),
+            ("path", "a b/c.d"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), Some(v.to_string())))
+        .collect();
+        assert_eq!(
+            parse(cmd),
+            Ok(Command::Wallpaper(
+                "My Wallpapers/forest #2".to_string(),
+                CmdDuration::Finite(Duration::from_secs(1)),
+                expected
+            ))
+        );
+        assert_eq!(
+            parse(r#""unclosed 1s"#).map_err(|err| err.kind),
+            Err(ParseError::InvalidArgument)
+        );
+        assert_eq!(
+            parse(r"bad\q 1s").map_err(|err| err.kind),
+            Err(ParseError::InvalidArgument)
+        );
+        assert_eq!(
+            parse_text(r##"'\'\"#tail"##),
+            Ok(("#tail", "\\\"".to_string()))
+        );
         let cmd = "114514 infinite ooh=hoo";
         let mut expected = HashMap::new();
-        expected.insert(String::from("ooh"), String::from("hoo"));
+        expected.insert(String::from("ooh"), Some(String::from("hoo")));
         assert_eq!(
             parse(cmd),
             Ok(Command::Wallpaper(
@@ -1,17 +1,30 @@
 //! Async tasks
 
-use nix::sys::signal::{Signal, kill};
+use chrono::Local;
+use nix::sys::signal::{Signal, killpg};
 use nix::unistd::Pid;
 use smol::channel::Receiver;
 use smol::process::Child;
-use std::time::{Duration, Instant};
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::time::{Duration, Instant, SystemTime};
 
 use crate::backend::Backend;
-use crate::runner::{Action, CmdDuration, Command, RunnerError};
+use crate::runner::{Action, CmdDuration, Command, PauseMode, RunnerError};
+use crate::utils::clock;
+use crate::utils::output::EngineLog;
+
+/// How often a deadline on the wall clock is checked again, see [`Execution::elapse`].
+const RECHECK_INTERVAL: Duration = Duration::from_secs(30);
 
 pub struct Execution {
     kind: ExecType,
     info: ExecInfo,
+    /// When the execution ends on the wall clock, for durations that are meant to end at some
+    /// time of day.
+    deadline: Option<SystemTime>,
+    /// When the execution is paused, [`None`] if it's not.
+    paused_at: Option<Instant>,
     interrupt_rx: Receiver<Action>,
 }
 
@@ -20,13 +33,26 @@ pub struct Execution {
 /// This data structure is only for long-running async tasks.
 /// Oneshot commands should be handled by [`Runner`] directly.
 enum ExecType {
-    Supervise { child: Child },
+    /// `sys_cmd` is kept to spawn `linux-wallpaperengine` again, once it's terminated by a pause.
+    /// `stopped` is true while its process group is stopped by SIGSTOP.
+    /// Output of every spawned child goes to `log`.
+    Supervise {
+        child: Child,
+        sys_cmd: Box<smol::process::Command>,
+        log: EngineLog,
+        cleared: bool,
+        stopped: bool,
+    },
     Sleep,
 }
 
 /// This struct contains status information for the current execution.
 #[derive(Clone)]
 pub struct ExecInfo {
+    /// The wallpaper being shown, if any.
+    pub(super) wallpaper: Option<String>,
+    /// How long the execution lasts, as picked for a [`CmdDuration::Range`] and cut short to the
+    /// limit.
     pub(super) duration: Option<Duration>,
     pub(super) start: Instant,
 }
@@ -36,65 +62,127 @@ pub enum ExecResult {
     Elapsed,
     /// User requested an [`Action`] to be done
     Interrupted(Action),
-    /// The backend program died unexpectedly, this should be an error
+    /// The backend program died unexpectedly, with its exit status if it could be collected
+    Died(Option<ExitStatus>),
+    /// The [`Action`] channel is closed, this should be an error
     Error,
 }
 
+/// Describes how `linux-wallpaperengine` exited, with its exit code or the signal that killed it.
+pub fn describe_exit(status: Option<ExitStatus>) -> String {
+    let Some(status) = status else {
+        return String::from("exited for an unknown reason");
+    };
+    if let Some(code) = status.code() {
+        format!("exited with code {code}")
+    } else if let Some(signal) = status.signal() {
+        match Signal::try_from(signal) {
+            Ok(signal) => format!("was killed by {signal}"),
+            Err(_) => format!("was killed by signal {signal}"),
+        }
+    } else {
+        String::from("exited for an unknown reason")
+    }
+}
+
+/// Picks a duration between `min` and `max` at random, both included, in milliseconds.
+fn pick_duration(rng: &mut fastrand::Rng, min: Duration, max: Duration) -> Duration {
+    let millis = |duration: Duration| u64::try_from(duration.as_millis()).unwrap_or(u64::MAX);
+    Duration::from_millis(rng.u64(millis(min)..=millis(max)))
+}
+
 impl Execution {
     /// Begins execution of a [`Command`].
     ///
     /// This immediately begins the execution, to get the result, `.await` on `.result()`.
-    pub fn begin(cmd: Command, backend: &Backend, interrupt_rx: Receiver<Action>) -> Self {
-        match cmd {
+    /// If `limit` is given, the execution lasts no longer than it.
+    /// `rng` picks the duration of a [`CmdDuration::Range`].
+    ///
+    /// # Errors
+    /// If `linux-wallpaperengine` cannot be spawned, returns [`RunnerError::CannotSpawn`].
+    pub fn begin(
+        cmd: Command,
+        backend: &Backend,
+        interrupt_rx: Receiver<Action>,
+        rng: &mut fastrand::Rng,
+        limit: Option<Duration>,
+    ) -> Result<Self, RunnerError> {
+        let (kind, wallpaper, duration) = match cmd {
             Command::Wallpaper(name, duration, properties) => {
                 let mut sys_cmd = backend.get_sys_command(&name, &properties);
-                // TODO: Error handling
-                let child = sys_cmd.spawn().unwrap();
-                match duration {
-                    CmdDuration::Finite(duration) => Self {
-                        kind: ExecType::Supervise { child },
-                        info: ExecInfo {
-                            duration: Some(duration),
-                            start: Instant::now(),
-                        },
-                        interrupt_rx,
-                    },
-                    CmdDuration::Infinite => Self {
-                        kind: ExecType::Supervise { child },
-                        info: ExecInfo {
-                            duration: None,
-                            start: Instant::now(),
-                        },
-                        interrupt_rx,
+                let log = backend.log();
+                let child = spawn(&mut sys_cmd, &log)?;
+                (
+                    ExecType::Supervise {
+                        child,
+                        sys_cmd: Box::new(sys_cmd),
+                        log,
+                        cleared: false,
+                        stopped: false,
                     },
-                }
+                    Some(name),
+                    duration,
+                )
             }
-            Command::Sleep(duration) => match duration {
-                CmdDuration::Finite(duration) => Self {
-                    kind: ExecType::Sleep,
-                    info: ExecInfo {
-                        duration: Some(duration),
-                        start: Instant::now(),
-                    },
-                    interrupt_rx,
-                },
-                CmdDuration::Infinite => Self {
-                    kind: ExecType::Sleep,
-                    info: ExecInfo {
-                        duration: None,
-                        start: Instant::now(),
-                    },
-                    interrupt_rx,
-                },
-            },
+            Command::Sleep(duration) => (ExecType::Sleep, None, duration),
             _ => unreachable!(),
-        }
+        };
+        let (duration, on_clock) = match duration {
+            CmdDuration::Finite(duration) => (Some(duration), false),
+            CmdDuration::Infinite => (None, false),
+            CmdDuration::Until(time) => (Some(clock::time_until(&Local::now(), time)), true),
+            CmdDuration::Range(min, max) => (Some(pick_duration(rng, min, max)), false),
+            CmdDuration::Aligned(period) => {
+                (Some(clock::time_to_boundary(&Local::now(), period)), true)
+            }
+        };
+        // The limit is the end of a `between` block, which is also a time of day
+        let (duration, on_clock) = match (duration, limit) {
+            (Some(duration), Some(limit)) if limit < duration => (Some(limit), true),
+            (None, Some(limit)) => (Some(limit), true),
+            (duration, _) => (duration, on_clock),
+        };
+        Ok(Self {
+            kind,
+            info: ExecInfo {
+                wallpaper,
+                duration,
+                start: Instant::now(),
+            },
+            deadline: duration
+                .filter(|_| on_clock)
+                .map(|duration| SystemTime::now() + duration),
+            paused_at: None,
+            interrupt_rx,
+        })
     }
 
     pub fn info(&self) -> ExecInfo {
         self.info.clone()
     }
 
+    /// Waits for the duration of the execution to elapse.
+    ///
+    /// Timers stop while the system is suspended, so a deadline on the wall clock is checked
+    /// against the clock every [`RECHECK_INTERVAL`] instead, so that it's not missed by the time
+    /// spent in suspend.
+    async fn elapse(end: Instant, deadline: Option<SystemTime>) -> ExecResult {
+        match deadline {
+            Some(deadline) => {
+                while let Ok(left) = deadline.duration_since(SystemTime::now()) {
+                    if left.is_zero() {
+                        break;
+                    }
+                    smol::Timer::after(left.min(RECHECK_INTERVAL)).await;
+                }
+            }
+            None => {
+                smol::Timer::at(end).await;
+            }
+        }
+        ExecResult::Elapsed
+    }
+
     /// Sleeps indefinitely and wait for an [`Action`].
     async fn wait_action(rx: &Receiver<Action>) -> ExecResult {
         match rx.recv().await {
@@ -103,30 +191,28 @@ impl Execution {
         }
     }
 
+    /// Waits for the execution to end, or an [`Action`] to interrupt it.
+    ///
+    /// This may be awaited again after an interruption, and the execution goes on where it was.
+    /// A paused execution only waits for an [`Action`].
     pub async fn result(&mut self) -> ExecResult {
+        if self.paused_at.is_some() {
+            return Self::wait_action(&self.interrupt_rx).await;
+        }
         match &mut self.kind {
-            ExecType::Supervise { child } => {
+            ExecType::Supervise { child, .. } => {
                 if let Some(duration) = self.info.duration {
+                    let end = self.info.start + duration;
                     smol::future::race(
-                        smol::future::race(
-                            async {
-                                smol::Timer::after(duration).await;
-                                ExecResult::Elapsed
-                            },
-                            async {
-                                let _ = child.status().await;
-                                ExecResult::Error
-                            },
-                        ),
+                        smol::future::race(Self::elapse(end, self.deadline), async {
+                            ExecResult::Died(child.status().await.ok())
+                        }),
                         Self::wait_action(&self.interrupt_rx),
                     )
                     .await
                 } else {
                     smol::future::race(
-                        async {
-                            let _ = child.status().await;
-                            ExecResult::Error
-                        },
+                        async { ExecResult::Died(child.status().await.ok()) },
                         Self::wait_action(&self.interrupt_rx),
                     )
                     .await
@@ -135,10 +221,7 @@ impl Execution {
             ExecType::Sleep => {
                 if let Some(duration) = self.info.duration {
                     smol::future::race(
-                        async {
-                            smol::Timer::after(duration).await;
-                            ExecResult::Elapsed
-                        },
+                        Self::elapse(self.info.start + duration, self.deadline),
                         Self::wait_action(&self.interrupt_rx),
                     )
                     .await
@@ -149,10 +232,123 @@ impl Execution {
         }
     }
 
+    /// Waits `delay` before `linux-wallpaperengine` is spawned again by [`Execution::respawn`],
+    /// or an [`Action`] to interrupt it.
+    pub async fn backoff(&self, delay: Duration) -> ExecResult {
+        smol::future::race(
+            async {
+                smol::Timer::after(delay).await;
+                ExecResult::Elapsed
+            },
+            Self::wait_action(&self.interrupt_rx),
+        )
+        .await
+    }
+
+    /// Spawns `linux-wallpaperengine` again after it died, for the rest of the duration.
+    ///
+    /// # Errors
+    /// If `linux-wallpaperengine` cannot be spawned again, returns [`RunnerError::CannotSpawn`].
+    pub fn respawn(&mut self) -> Result<(), RunnerError> {
+        if let ExecType::Supervise {
+            child,
+            sys_cmd,
+            log,
+            stopped,
+            ..
+        } = &mut self.kind
+        {
+            *child = spawn(sys_cmd, log)?;
+            *stopped = false;
+        }
+        Ok(())
+    }
+
     pub fn remaining(&self) -> Option<Duration> {
-        let end = Instant::now();
+        if let Some(deadline) = self.deadline {
+            return Some(
+                deadline
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default(),
+            );
+        }
+        let end = self.paused_at.unwrap_or_else(Instant::now);
         let actual = end.duration_since(self.info.start);
-        self.info.duration.map(|expected| expected - actual)
+        self.info
+            .duration
+            .map(|expected| expected.saturating_sub(actual))
+    }
+
+    /// Whether the execution is paused.
+    pub fn paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    /// Returns whether `linux-wallpaperengine` is stopped by [`PauseMode::Freeze`].
+    pub fn frozen(&self) -> bool {
+        matches!(self.kind, ExecType::Supervise { stopped: true, .. })
+    }
+
+    /// Stops the timer, so that the execution only waits for an [`Action`].
+    /// `linux-wallpaperengine` is kept, terminated or frozen according to `mode`.
+    pub fn pause(&mut self, mode: PauseMode) -> Result<(), RunnerError> {
+        self.paused_at.get_or_insert_with(Instant::now);
+        let ExecType::Supervise {
+            child,
+            cleared,
+            stopped,
+            ..
+        } = &mut self.kind
+        else {
+            return Ok(());
+        };
+        match mode {
+            PauseMode::Keep => Ok(()),
+            PauseMode::Clear => {
+                *cleared = true;
+                self.cleanup()
+            }
+            PauseMode::Freeze
+                if !*cleared && !*stopped && child.try_status().is_ok_and(|s| s.is_none()) =>
+            {
+                killpg(child_pid(child), Signal::SIGSTOP).map_err(|_| RunnerError::SignalFailed)?;
+                *stopped = true;
+                Ok(())
+            }
+            PauseMode::Freeze => Ok(()),
+        }
+    }
+
+    /// Starts the timer again with the time that was remaining when paused.
+    ///
+    /// A deadline on the wall clock is kept as is.
+    /// If `linux-wallpaperengine` is terminated by [`Execution::pause`], or has exited meanwhile,
+    /// it's spawned again.
+    ///
+    /// # Errors
+    /// If `linux-wallpaperengine` cannot be spawned again, returns [`RunnerError::CannotSpawn`].
+    pub fn resume(&mut self) -> Result<(), RunnerError> {
+        if let Some(paused_at) = self.paused_at.take() {
+            self.info.start += paused_at.elapsed();
+        }
+        if let ExecType::Supervise {
+            child,
+            sys_cmd,
+            log,
+            cleared,
+            stopped,
+        } = &mut self.kind
+        {
+            if *stopped {
+                killpg(child_pid(child), Signal::SIGCONT).map_err(|_| RunnerError::SignalFailed)?;
+                *stopped = false;
+            }
+            if *cleared || child.try_status().is_ok_and(|status| status.is_some()) {
+                *child = spawn(sys_cmd, log)?;
+                *cleared = false;
+            }
+        }
+        Ok(())
     }
 
     /// Kills the child process.
@@ -160,18 +356,22 @@ impl Execution {
     /// This consumes the execution.
     pub fn cleanup(&mut self) -> Result<(), RunnerError> {
         match &mut self.kind {
-            ExecType::Supervise { child } => {
+            ExecType::Supervise { child, stopped, .. } => {
                 // If the child is still alive. We send a SIGTERM instead of a SIGKILL.
                 if child
                     .try_status()
                     .map_err(|_| RunnerError::CleanupFail)?
                     .is_none()
                 {
-                    let pid =
-                        Pid::from_raw(child.id().try_into().expect("PID should not be that large"));
-
-                    kill(Pid::from_raw(pid.into()), Signal::SIGTERM)
-                        .map_err(|_| RunnerError::CleanupFail)
+                    let pid = child_pid(child);
+                    // The whole group, so that helpers it spawned go away with it
+                    killpg(pid, Signal::SIGTERM).map_err(|_| RunnerError::CleanupFail)?;
+                    // A stopped process only handles SIGTERM once it's continued
+                    if *stopped {
+                        killpg(pid, Signal::SIGCONT).map_err(|_| RunnerError::CleanupFail)?;
+                        *stopped = false;
+                    }
+                    Ok(())
                 } else {
                     Ok(())
                 }
@@ -180,3 +380,71 @@ impl Execution {
         }
     }
 }
+
+/// Spawns `linux-wallpaperengine`, with its output going to `log`.
+fn spawn(sys_cmd: &mut smol::process::Command, log: &EngineLog) -> Result<Child, RunnerError> {
+    let mut child = sys_cmd
+        .spawn()
+        .map_err(|err| RunnerError::CannotSpawn(err.to_string()))?;
+    log.attach(&mut child);
+    Ok(child)
+}
+
+/// The PID of the child, which is also the ID of its process group.
+fn child_pid(child: &Child) -> Pid {
+    Pid::from_raw(child.id().try_into().expect("PID should not be that large"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_duration() {
+        let (min, max) = (Duration::from_secs(600), Duration::from_secs(1200));
+        let mut rng = fastrand::Rng::with_seed(0);
+        for _ in 0..100 {
+            let picked = pick_duration(&mut rng, min, max);
+            assert!(min <= picked && picked <= max);
+        }
+        assert_eq!(pick_duration(&mut rng, min, min), min);
+        // The same seed picks the same durations
+        let mut rng = fastrand::Rng::with_seed(7);
+        let mut again = fastrand::Rng::with_seed(7);
+        assert_eq!(
+            pick_duration(&mut rng, min, max),
+            pick_duration(&mut again, min, max)
+        );
+    }
+
+    #[test]
+    fn exit_description() {
+        assert_eq!(
+            describe_exit(Some(ExitStatus::from_raw(1 << 8))),
+            "exited with code 1"
+        );
+        assert_eq!(
+            describe_exit(Some(ExitStatus::from_raw(11))),
+            "was killed by SIGSEGV"
+        );
+        assert_eq!(describe_exit(None), "exited for an unknown reason");
+    }
+
+    #[test]
+    fn pause_and_resume() {
+        let (_tx, rx) = smol::channel::unbounded();
+        let cmd = Command::Sleep(CmdDuration::Finite(Duration::from_secs(60)));
+        let backend = Backend::new(None, EngineLog::default());
+        let mut rng = fastrand::Rng::with_seed(0);
+        let mut exec = Execution::begin(cmd, &backend, rx, &mut rng, None).unwrap();
+        exec.pause(PauseMode::Keep).unwrap();
+        assert!(exec.paused());
+        let remaining = exec.remaining().unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        // The timer stops while paused
+        assert_eq!(exec.remaining(), Some(remaining));
+        exec.resume().unwrap();
+        assert!(!exec.paused());
+        assert!(exec.remaining().unwrap() > Duration::from_secs(59));
+    }
+}
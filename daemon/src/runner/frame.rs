@@ -0,0 +1,218 @@
+//! A frame is a list of [`Command`]s being executed, with the position in it.
+//!
+//! The loaded playlist is the bottom frame of a [`Runner`],
+//! called playlists and entered blocks are pushed on top of it.
+
+use chrono::NaiveTime;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use crate::runner::{CmdDuration, Command};
+use crate::utils::clock;
+
+/// A playlist, or a block in it, being executed.
+pub(super) struct Frame {
+    pub(super) path: PathBuf,
+    /// Name of the block, [`None`] if this is a whole playlist.
+    pub(super) block: Option<&'static str>,
+    pub(super) index: usize,
+    pub(super) commands: Vec<Command>,
+    /// Remaining jumps of bounded `goto`s, keyed by their index.
+    pub(super) counters: BTreeMap<usize, usize>,
+    /// Index of the command to execute at each position, when the playlist is shuffled.
+    pub(super) order: Option<Vec<usize>>,
+    /// Start and end time of a `between` block, outside which the frame is left.
+    pub(super) window: Option<(NaiveTime, NaiveTime)>,
+    /// Default properties of a `{ ... }` block, which are dropped along with the frame, so that
+    /// the outer ones are restored.
+    pub(super) defaults: Option<HashMap<String, String>>,
+    /// Wallpapers found by a `dir`, played instead of the commands.
+    pub(super) dir: Option<DirEntries>,
+}
+
+/// Wallpapers found by a `dir`, each played with the same duration and properties.
+///
+/// Their [`Command::Wallpaper`]s are only built once reached.
+pub(super) struct DirEntries {
+    pub(super) paths: Vec<PathBuf>,
+    pub(super) duration: CmdDuration,
+    pub(super) props: HashMap<String, Option<String>>,
+}
+
+impl Frame {
+    pub(super) fn new(path: PathBuf, commands: Vec<Command>, index: usize) -> Self {
+        Self {
+            path,
+            block: None,
+            index,
+            commands,
+            counters: BTreeMap::new(),
+            order: None,
+            window: None,
+            defaults: None,
+            dir: None,
+        }
+    }
+
+    /// Number of positions in this frame.
+    pub(super) fn len(&self) -> usize {
+        match &self.dir {
+            Some(dir) => dir.paths.len(),
+            None => self.commands.len(),
+        }
+    }
+
+    /// Index of the command at the current position.
+    pub(super) fn current_index(&self) -> usize {
+        self.order
+            .as_ref()
+            .and_then(|order| order.get(self.index).copied())
+            .unwrap_or(self.index)
+    }
+
+    /// The command at the current position.
+    pub(super) fn current(&self) -> Option<Command> {
+        match &self.dir {
+            Some(dir) => dir.paths.get(self.index).map(|path| {
+                Command::Wallpaper(
+                    path.to_string_lossy().to_string(),
+                    dir.duration.clone(),
+                    dir.props.clone(),
+                )
+            }),
+            None => self.commands.get(self.current_index()).cloned(),
+        }
+    }
+
+    /// Whether this frame should still be executed at the given time.
+    pub(super) fn open_at(&self, now: NaiveTime) -> bool {
+        self.window
+            .is_none_or(|(start, end)| clock::in_window(now, start, end))
+    }
+
+    /// Whether `order` is one that [`Frame::shuffle`] could have made for this frame, which is
+    /// not the case for an order saved before the playlist changed.
+    pub(super) fn fits(&self, order: &[usize]) -> bool {
+        let is_wallpaper =
+            |index: usize| matches!(self.commands.get(index), Some(Command::Wallpaper(..)));
+        let mut seen = vec![false; self.commands.len()];
+        order.len() == self.commands.len()
+            && order.iter().enumerate().all(|(slot, &index)| {
+                let kept = if is_wallpaper(slot) {
+                    is_wallpaper(index)
+                } else {
+                    index == slot
+                };
+                // Each wallpaper is played once
+                kept && !std::mem::replace(&mut seen[index], true)
+            })
+    }
+
+    /// Puts wallpapers in a new random order, other commands stay where they are.
+    ///
+    /// Unless there is only one wallpaper, the new order does not begin with the wallpaper that
+    /// ends the previous one, so that no wallpaper is played twice in a row.
+    pub(super) fn shuffle(&mut self, rng: &mut fastrand::Rng) {
+        let slots: Vec<usize> = self
+            .commands
+            .iter()
+            .enumerate()
+            .filter(|(_, cmd)| matches!(cmd, Command::Wallpaper(..)))
+            .map(|(index, _)| index)
+            .collect();
+        let last = self
+            .order
+            .as_ref()
+            .zip(slots.last())
+            .map(|(order, slot)| order[*slot]);
+
+        let mut shuffled = slots.clone();
+        rng.shuffle(&mut shuffled);
+        if shuffled.len() > 1 && shuffled.first() == last.as_ref() {
+            let swap = rng.usize(1..shuffled.len());
+            shuffled.swap(0, swap);
+        }
+
+        let mut order: Vec<usize> = (0..self.commands.len()).collect();
+        for (slot, index) in slots.into_iter().zip(shuffled) {
+            order[slot] = index;
+        }
+        self.order = Some(order);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::CmdDuration;
+
+    #[test]
+    fn shuffle_wallpapers() {
+        let wallpaper = |id: &str| {
+            Command::Wallpaper(id.to_string(), CmdDuration::Infinite, Default::default())
+        };
+        let mut frame = Frame::new(
+            PathBuf::new(),
+            vec![
+                Command::Default(Default::default()),
+                wallpaper("1"),
+                wallpaper("2"),
+                Command::Sleep(CmdDuration::Infinite),
+                wallpaper("3"),
+            ],
+            0,
+        );
+        let mut rng = fastrand::Rng::with_seed(7);
+        for _ in 0..32 {
+            let last = frame.order.as_ref().map(|order| order[4]);
+            frame.shuffle(&mut rng);
+            let order = frame.order.clone().unwrap();
+
+            // Other commands stay in place
+            assert_eq!(order[0], 0);
+            assert_eq!(order[3], 3);
+            // Every wallpaper is played once
+            let mut played = vec![order[1], order[2], order[4]];
+            played.sort_unstable();
+            assert_eq!(played, [1, 2, 4]);
+            // No repeat across cycles
+            assert_ne!(Some(order[1]), last);
+        }
+    }
+
+    #[test]
+    fn saved_order() {
+        let wallpaper = |id: &str| {
+            Command::Wallpaper(id.to_string(), CmdDuration::Infinite, Default::default())
+        };
+        let frame = Frame::new(
+            PathBuf::new(),
+            vec![
+                wallpaper("1"),
+                Command::Sleep(CmdDuration::Infinite),
+                wallpaper("2"),
+            ],
+            0,
+        );
+        assert!(frame.fits(&[2, 1, 0]));
+        assert!(frame.fits(&[0, 1, 2]));
+        // A wallpaper is moved onto another command
+        assert!(!frame.fits(&[1, 0, 2]));
+        // A wallpaper is played twice
+        assert!(!frame.fits(&[2, 1, 2]));
+        assert!(!frame.fits(&[3, 1, 0]));
+        assert!(!frame.fits(&[2, 1]));
+    }
+
+    #[test]
+    fn window() {
+        let time = |h| NaiveTime::from_hms_opt(h, 0, 0).unwrap();
+        let mut frame = Frame::new(PathBuf::new(), vec![Command::End], 0);
+        assert!(frame.open_at(time(12)));
+        frame.window = Some((time(22), time(6)));
+        assert!(frame.open_at(time(23)));
+        assert!(frame.open_at(time(2)));
+        assert!(!frame.open_at(time(6)));
+        assert!(!frame.open_at(time(12)));
+    }
+}
@@ -7,16 +7,29 @@
 //! 4. Handle the [`ExecResult`] reported by the [`Execution`] future.
 
 use async_recursion::async_recursion;
+use chrono::Local;
 use smol::lock::Mutex;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
-use crate::backend::Backend;
-use crate::runner::exec::{ExecResult, Execution};
+use crate::backend::{self, Backend};
+use crate::daemon::{CACHE_PATH, CFG, WORKSHOP};
+use crate::runner::exec::{self, ExecInfo, ExecResult, Execution};
 use crate::runner::{
-    Action, Command, NOMONITOR_INDICATOR, Runner, RunnerError, RunnerHandle, State,
+    Action, CmdDuration, Command, DryRun, Frame, NOMONITOR_INDICATOR, Runner, RunnerError,
+    RunnerHandle, State, Target, read_called, read_handed,
 };
-use crate::utils::playlist;
+use crate::utils::clock;
+use crate::utils::output::EngineLog;
+use crate::utils::playlist::{self, Playlist};
+use crate::utils::state::SavedState;
+use crate::utils::workshop;
+
+/// How long to wait before the first restart of a crashed `linux-wallpaperengine`,
+/// doubled for each restart after.
+const RESTART_BACKOFF: Duration = Duration::from_secs(1);
 
 /// A flag to break the outer loop.
 enum LoopFlag {
@@ -26,45 +39,26 @@ enum LoopFlag {
 }
 
 impl Runner {
-    /// Creates a new Runner that operates the given playlist, whose initial index is given.
+    /// Creates a new Runner that operates the given playlist, resuming from the given state.
     ///
     /// The special monitor name "NOMONITOR" is to indicate this runner has no associated monitor.
+    /// If the playlist declares a monitor, it is used in place of "NOMONITOR".
+    /// The playlist is shuffled if `shuffle` is true, or the playlist declares so.
     ///
     /// # Errors
     /// If the given playlist cannot be parsed, or is empty, this will return [`RunnerError::InitFailed`].
-    pub fn from_index(
+    /// If the playlist declares another monitor than the given one, this will return
+    /// [`RunnerError::MonitorConflict`].
+    pub fn from_state(
         monitor: String,
         path: PathBuf,
-        index: usize,
+        state: SavedState,
+        shuffle: bool,
     ) -> Result<(Self, Arc<Mutex<RunnerHandle>>), RunnerError> {
-        let monitor = if monitor == NOMONITOR_INDICATOR {
-            None
-        } else {
-            Some(monitor)
-        };
-
         match playlist::open(&path) {
             Ok(file) => {
-                let (tx, rx) = smol::channel::unbounded();
-                let commands = playlist::parse(&path, &file).ok_or(RunnerError::InitFailed)?;
-                let backend = Backend::new(monitor);
-
-                let handle = Arc::new(Mutex::new(RunnerHandle {
-                    index,
-                    commands,
-                    state: State::Ready,
-                    path,
-                    tx,
-                }));
-
-                Ok((
-                    Self {
-                        internal: handle.clone(),
-                        backend,
-                        rx,
-                    },
-                    handle,
-                ))
+                let parsed = playlist::parse(&path, &file).ok_or(RunnerError::InitFailed)?;
+                Self::from_playlist(monitor, path, parsed, state, shuffle)
             }
             Err(err) => {
                 log::error!("{err}");
@@ -73,29 +67,234 @@ impl Runner {
         }
     }
 
+    /// Same as [`Runner::from_state`], but with a playlist already parsed from `path`.
+    ///
+    /// # Errors
+    /// If the playlist is empty, this will return [`RunnerError::InitFailed`].
+    /// If the playlist declares another monitor than the given one, this will return
+    /// [`RunnerError::MonitorConflict`].
+    pub fn from_playlist(
+        monitor: String,
+        path: PathBuf,
+        parsed: Playlist,
+        state: SavedState,
+        shuffle: bool,
+    ) -> Result<(Self, Arc<Mutex<RunnerHandle>>), RunnerError> {
+        let monitor = resolve_monitor(monitor, parsed.monitor.clone())?;
+        let mirror = CFG
+            .mirror_logs
+            .then(|| CACHE_PATH.join(format!("{monitor}.log")));
+        let logs = EngineLog::new(mirror.as_deref());
+        Self::assemble(monitor, path, parsed, state, shuffle, logs)
+    }
+
+    /// Builds a Runner for an already resolved monitor, writing the output of
+    /// `linux-wallpaperengine` to `logs`.
+    ///
+    /// # Errors
+    /// If the playlist is empty, this will return [`RunnerError::InitFailed`].
+    fn assemble(
+        monitor: String,
+        path: PathBuf,
+        parsed: Playlist,
+        state: SavedState,
+        shuffle: bool,
+        logs: EngineLog,
+    ) -> Result<(Self, Arc<Mutex<RunnerHandle>>), RunnerError> {
+        if parsed.commands.is_empty() {
+            return Err(RunnerError::InitFailed);
+        }
+        let (tx, rx) = smol::channel::unbounded();
+        let backend = Backend::new(
+            if monitor == NOMONITOR_INDICATOR {
+                None
+            } else {
+                Some(monitor.clone())
+            },
+            logs.clone(),
+        );
+
+        let mut rng = state
+            .seed
+            .map_or_else(fastrand::Rng::new, fastrand::Rng::with_seed);
+        let mut frame = Frame::new(path, parsed.commands, state.index);
+        if shuffle || parsed.shuffle {
+            // Keep the saved order unless the playlist has changed
+            match state.order {
+                Some(order) if frame.fits(&order) => {
+                    frame.order = Some(order);
+                }
+                _ => frame.shuffle(&mut rng),
+            }
+        }
+        let handle = Arc::new(Mutex::new(RunnerHandle {
+            stack: vec![frame],
+            state: State::Ready,
+            monitor,
+            seed: rng.get_seed(),
+            rng,
+            last_crash: None,
+            bad: HashSet::new(),
+            failures: HashMap::new(),
+            passes: 0,
+            logs,
+            tx,
+        }));
+
+        Ok((
+            Self {
+                internal: handle.clone(),
+                backend,
+                rx,
+                last: None,
+                // A resumed playlist may begin past everything it shows
+                shown: true,
+                dry: None,
+            },
+            handle,
+        ))
+    }
+
     /// The main runner task.
     pub async fn run(&mut self) {
+        let mut exit_state = State::Exited;
         loop {
             // Fetch current command,
             // By default go back to the beginning when reached the end
-            let current_cmd = {
+            let (current_cmd, wrapped) = {
                 let mut internal = self.internal.lock().await;
-                if internal.index >= internal.commands.len() {
-                    internal.index = 0;
-                }
-                let Some(current_cmd) = internal.commands.get(internal.index).cloned() else {
+                let passes = internal.passes;
+                let Some(current_cmd) = internal.fetch() else {
                     log::error!("Got invalid command");
-                    internal.index += 1;
+                    internal.frame_mut().index += 1;
                     continue;
                 };
-                current_cmd
+                (current_cmd, internal.passes != passes)
             };
+            if wrapped && !self.start_over().await {
+                break;
+            }
 
             // Process current command
             match current_cmd {
-                Command::Default(props) => self.backend.update_default_props(props),
+                Command::Default(props) => *self.internal.lock().await.defaults_mut() = props,
+                Command::UpdateDefault(changes) => {
+                    let mut internal = self.internal.lock().await;
+                    let defaults = internal.defaults_mut();
+                    *defaults = backend::combine(defaults, &changes);
+                }
                 Command::End => break,
-                cmd => match self.exec_async(cmd).await {
+                Command::Unload => {
+                    exit_state = State::Unloaded;
+                    break;
+                }
+                Command::Loop(times) => {
+                    let mut internal = self.internal.lock().await;
+                    if internal.take_goto(times) {
+                        internal.restart();
+                        drop(internal);
+                        // An endless loop never reaches the end of the playlist
+                        if times.is_none() && !self.start_over().await {
+                            break;
+                        }
+                        continue;
+                    }
+                }
+                Command::Then(path) => {
+                    let from = self.internal.lock().await.frame().path.clone();
+                    let handed = match read_handed(&path, &from) {
+                        Ok((found, parsed)) => self.internal.lock().await.hand_over(found, parsed),
+                        Err(err) => Err(err),
+                    };
+                    match handed {
+                        Ok(()) => {
+                            log::info!("Handed over to `{}`", path.to_string_lossy());
+                            continue;
+                        }
+                        Err(err) => log::error!("{err}: `{}`", path.to_string_lossy()),
+                    }
+                }
+                Command::Stop => {
+                    // Show the last wallpaper again, as it's already terminated
+                    let cmd = match &self.last {
+                        Some(Command::Wallpaper(id, _, props)) => {
+                            Command::Wallpaper(id.clone(), CmdDuration::Infinite, props.clone())
+                        }
+                        _ => Command::Sleep(CmdDuration::Infinite),
+                    };
+                    match self.exec_async(cmd, State::Stopped).await {
+                        LoopFlag::Nothing => (),
+                        LoopFlag::Break => break,
+                        LoopFlag::Continue => continue,
+                    }
+                }
+                Command::Goto(Target::Index(target), times) => {
+                    let jumped = self.internal.lock().await.jump(target, times);
+                    if let Some(started_over) = jumped {
+                        if started_over && !self.start_over().await {
+                            break;
+                        }
+                        continue;
+                    }
+                }
+                Command::Goto(target, _) => log::error!("Unresolved goto target {target:?}"),
+                Command::Label(_) => (),
+                Command::Include(path) => {
+                    log::error!("Unexpanded include of `{}`", path.to_string_lossy());
+                }
+                Command::Set(name, _) => log::error!("Unexpanded variable `{name}`"),
+                Command::Monitor(_) | Command::Shuffle => {
+                    log::error!("Misplaced header directive");
+                }
+                Command::Choose(count, entries) => {
+                    let mut internal = self.internal.lock().await;
+                    let picked = internal.pick(count, entries);
+                    internal.enter_block("random", picked);
+                    continue;
+                }
+                Command::Between(start, end, commands) => {
+                    if clock::in_window(Local::now().time(), start, end) {
+                        self.internal
+                            .lock()
+                            .await
+                            .enter_window(start, end, commands);
+                        continue;
+                    }
+                }
+                Command::Scope(commands) => {
+                    self.internal.lock().await.enter_scope(commands);
+                    continue;
+                }
+                Command::When(conditions, commands) => {
+                    let today = Local::now().date_naive();
+                    if conditions.iter().all(|condition| condition.holds(today)) {
+                        self.internal.lock().await.enter_block("when", commands);
+                        continue;
+                    }
+                }
+                Command::Dir(path, duration, order, props) => {
+                    let scanned = path.clone();
+                    match smol::unblock(move || workshop::scan(&scanned)).await {
+                        Ok(found) => {
+                            self.internal
+                                .lock()
+                                .await
+                                .enter_dir(&path, found, duration, order, props);
+                            continue;
+                        }
+                        Err(err) => {
+                            log::error!("Cannot scan `{}`: {err}", path.to_string_lossy());
+                        }
+                    }
+                }
+                Command::Call(path) => {
+                    let stack = self.internal.lock().await.paths();
+                    if let Ok((found, parsed)) = read_called(&path, &stack) {
+                        self.internal.lock().await.call(found, parsed);
+                        continue;
+                    }
+                }
+                cmd => match self.exec_async(cmd, State::Running).await {
                     LoopFlag::Nothing => (),
                     LoopFlag::Break => break,
                     LoopFlag::Continue => continue,
@@ -103,44 +302,309 @@ impl Runner {
             }
             self.next().await;
         }
-        self.update_state(State::Exited).await;
+        self.update_state(exit_state).await;
+    }
+
+    /// Called when the playlist starts over, which is when it goes back to its beginning or
+    /// repeats endlessly with `loop` or `goto`.
+    ///
+    /// Without this, a playlist whose wallpapers are all skipped would spin without ever waiting.
+    /// Returns false if the runner should exit.
+    async fn start_over(&mut self) -> bool {
+        // A dry run has nothing to wait for
+        std::mem::replace(&mut self.shown, false) || (self.dry.is_none() && self.idle().await)
+    }
+
+    /// Waits for an [`Action`] after a whole pass of the playlist has shown nothing.
+    ///
+    /// Wallpapers skipped for crashing are tried again, unless the action is [`Action::Exit`],
+    /// in which case this returns false.
+    async fn idle(&mut self) -> bool {
+        let reason = String::from("Nothing in the playlist can be shown");
+        log::error!("{reason}");
+        self.update_state(State::Failed(reason)).await;
+        loop {
+            match self.rx.recv().await {
+                Ok(Action::Exit) | Err(_) => return false,
+                Ok(Action::Pause(_)) => (),
+                Ok(_) => break,
+            }
+        }
+        self.internal.lock().await.forgive();
+        // Give the playlist a whole pass again
+        self.shown = true;
+        true
     }
 
     /// Handles long-running tasks
+    ///
+    /// `state` makes the [`State`] reported during the execution.
     #[async_recursion]
-    async fn exec_async(&mut self, cmd: Command) -> LoopFlag {
-        let mut exec = Execution::begin(cmd, &self.backend, self.rx.clone());
-        self.update_state(State::Running(exec.info())).await;
-        let result = exec.result().await;
-        let flag = match result {
-            ExecResult::Elapsed => LoopFlag::Nothing,
-            ExecResult::Error => LoopFlag::Nothing,
-            ExecResult::Interrupted(action) => match action {
-                Action::Next => LoopFlag::Nothing,
-                Action::Prev => {
-                    self.prev().await;
-                    LoopFlag::Continue
-                }
-                Action::Goto(i) => {
-                    self.goto(i).await;
-                    LoopFlag::Continue
-                }
-                Action::Exec(cmd) => {
-                    let _ = exec.cleanup();
-                    return self.exec_async(cmd).await;
-                }
-                Action::Pause(clear) => {
-                    if clear {
-                        let _ = exec.cleanup();
+    async fn exec_async(&mut self, cmd: Command, state: fn(ExecInfo) -> State) -> LoopFlag {
+        let original = cmd.clone();
+        // A query matching nothing waits for its duration, the same as a failed spawn below
+        let (cmd, unmatched) = match cmd {
+            Command::Query(criteria, duration, props) => {
+                let library = WORKSHOP.read().await;
+                match self.internal.lock().await.query(&library, &criteria) {
+                    Some(id) => (
+                        Command::Wallpaper(
+                            id,
+                            duration,
+                            props
+                                .into_iter()
+                                .map(|(key, value)| (key, Some(value)))
+                                .collect(),
+                        ),
+                        None,
+                    ),
+                    None => {
+                        let reason = format!("No installed wallpaper matches {criteria:?}");
+                        log::warn!("{reason}");
+                        (Command::Sleep(duration), Some(reason))
                     }
-                    self.update_state(State::Paused(exec.remaining())).await;
-                    let _ = self.rx.recv().await;
-                    return LoopFlag::Nothing;
                 }
-                Action::Exit => LoopFlag::Break,
-            },
+            }
+            cmd => (cmd, None),
+        };
+        let (limit, mut rng) = {
+            let mut internal = self.internal.lock().await;
+            self.backend.update_default_props(internal.defaults());
+            (internal.time_left(), internal.rng.fork())
+        };
+        if let Command::Wallpaper(id, ..) = &cmd {
+            if self.internal.lock().await.bad.contains(id) {
+                log::warn!("Skipping `{id}`, which keeps crashing");
+                return LoopFlag::Nothing;
+            }
+            self.last = Some(cmd.clone());
+        }
+        if let Some(dry) = &mut self.dry {
+            dry.shown.push(dry_entry(&cmd));
+            self.shown = true;
+            return if dry.shown.len() >= dry.limit {
+                LoopFlag::Break
+            } else {
+                LoopFlag::Nothing
+            };
+        }
+        // If `linux-wallpaperengine` cannot be spawned, wait for the rest of the entry instead,
+        // so that it can be retried with `play`, or skipped
+        let (mut exec, failed) =
+            match Execution::begin(cmd.clone(), &self.backend, self.rx.clone(), &mut rng, limit) {
+                Ok(exec) => (exec, unmatched),
+                Err(err) => {
+                    log::error!("{err}");
+                    let duration = match &cmd {
+                        Command::Wallpaper(_, duration, _) => duration.clone(),
+                        _ => CmdDuration::Infinite,
+                    };
+                    let sleep = Command::Sleep(duration);
+                    let exec =
+                        Execution::begin(sleep, &self.backend, self.rx.clone(), &mut rng, limit)
+                            .expect("Sleeping should not fail");
+                    (exec, Some(err.to_string()))
+                }
+            };
+        let report = |exec: &Execution| match &failed {
+            Some(reason) => State::Failed(reason.clone()),
+            None => state(exec.info()),
+        };
+        self.update_state(report(&exec)).await;
+        self.shown = true;
+        let mut restarts = 0;
+        let mut crashed = false;
+        let mut retry = None;
+        let flag = loop {
+            let result = match retry.take() {
+                Some(delay) => match exec.backoff(delay).await {
+                    ExecResult::Elapsed => match exec.respawn() {
+                        Ok(()) => continue,
+                        Err(err) => {
+                            log::error!("{err}");
+                            break LoopFlag::Nothing;
+                        }
+                    },
+                    result => result,
+                },
+                None => exec.result().await,
+            };
+            match result {
+                ExecResult::Elapsed => {
+                    if !crashed && let Some(id) = exec.info().wallpaper {
+                        self.internal.lock().await.record_slot(&id, false);
+                    }
+                    break LoopFlag::Nothing;
+                }
+                ExecResult::Died(status) => {
+                    crashed = true;
+                    let id = exec.info().wallpaper.unwrap_or_default();
+                    let reason = exec::describe_exit(status);
+                    log::warn!(
+                        "{} while showing `{id}`",
+                        RunnerError::EngineDied(reason.clone())
+                    );
+                    let mut internal = self.internal.lock().await;
+                    internal.last_crash = Some(format!("`{id}` {reason}"));
+                    if restarts >= CFG.restarts {
+                        if internal.record_slot(&id, true) {
+                            log::error!("`{id}` keeps crashing, skipping it for this session");
+                        }
+                        break LoopFlag::Nothing;
+                    }
+                    let delay = restart_delay(restarts);
+                    // Not worth restarting if the slot ends before the backoff does
+                    if exec.remaining().is_some_and(|left| left <= delay) {
+                        break LoopFlag::Nothing;
+                    }
+                    restarts += 1;
+                    retry = Some(delay);
+                }
+                ExecResult::Error => break LoopFlag::Nothing,
+                ExecResult::Interrupted(action) => match action {
+                    Action::Next => break LoopFlag::Nothing,
+                    Action::Prev => {
+                        self.prev().await;
+                        break LoopFlag::Continue;
+                    }
+                    Action::Goto(i) => {
+                        self.goto(i).await;
+                        break LoopFlag::Continue;
+                    }
+                    Action::Exec(cmd) => {
+                        let _ = exec.cleanup();
+                        return self.exec_async(cmd, State::Running).await;
+                    }
+                    Action::Pause(mode) => {
+                        if let Err(err) = exec.pause(mode) {
+                            log::error!("Failed to pause `linux-wallpaperengine`: {err}");
+                        }
+                        let remaining = exec.remaining();
+                        if exec.frozen() {
+                            self.update_state(State::Frozen(remaining)).await;
+                        } else {
+                            self.update_state(State::Paused(remaining)).await;
+                        }
+                    }
+                    Action::Resume => {
+                        if failed.is_some() {
+                            let _ = exec.cleanup();
+                            return self.exec_async(original, state).await;
+                        } else if exec.paused() {
+                            if let Err(err) = exec.resume() {
+                                log::error!("{err}");
+                                break LoopFlag::Nothing;
+                            }
+                            self.update_state(report(&exec)).await;
+                        } else if matches!(self.internal.lock().await.state, State::Stopped(_)) {
+                            break LoopFlag::Nothing;
+                        }
+                    }
+                    Action::Exit => break LoopFlag::Break,
+                },
+            }
         };
         let _ = exec.cleanup();
         flag
     }
 }
+
+/// Runs the playlist at `path` without spawning `linux-wallpaperengine` or waiting for anything,
+/// and returns the wallpapers and sleeps it goes through, such as `1 for 900s` or `sleep 300s`.
+///
+/// The run stops after `limit` of them, or when the playlist exits by itself.
+///
+/// # Errors
+/// If the playlist cannot be parsed, or is empty, this will return [`RunnerError::InitFailed`].
+pub fn dry_run(path: &Path, limit: usize) -> Result<Vec<String>, RunnerError> {
+    let file = playlist::open(path).map_err(|err| {
+        log::error!("{err}");
+        RunnerError::InitFailed
+    })?;
+    let parsed = playlist::parse(path, &file).ok_or(RunnerError::InitFailed)?;
+    let monitor = parsed
+        .monitor
+        .clone()
+        .unwrap_or_else(|| NOMONITOR_INDICATOR.to_string());
+    let (mut runner, _) = Runner::assemble(
+        monitor,
+        path.to_path_buf(),
+        parsed,
+        SavedState::default(),
+        false,
+        EngineLog::new(None),
+    )?;
+    runner.dry = Some(DryRun {
+        shown: Vec::new(),
+        limit,
+    });
+    smol::block_on(runner.run());
+    Ok(runner.dry.map(|dry| dry.shown).unwrap_or_default())
+}
+
+/// Describes a wallpaper or sleep for [`dry_run`].
+fn dry_entry(cmd: &Command) -> String {
+    let span = |duration: &CmdDuration| match duration {
+        CmdDuration::Finite(length) => format!("{length:?}"),
+        other => format!("{other:?}"),
+    };
+    match cmd {
+        Command::Wallpaper(id, duration, _) => format!("{id} for {}", span(duration)),
+        Command::Sleep(duration) => format!("sleep {}", span(duration)),
+        cmd => format!("{cmd:?}"),
+    }
+}
+
+/// How long to wait before restarting a crashed `linux-wallpaperengine`, after it has been
+/// restarted `restarts` times in the same slot.
+fn restart_delay(restarts: u32) -> Duration {
+    RESTART_BACKOFF.saturating_mul(2u32.saturating_pow(restarts))
+}
+
+/// Decides which monitor a runner should use.
+///
+/// The monitor requested by the user takes precedence over "NOMONITOR", but not over the one
+/// declared by the playlist. Requesting another monitor than the declared one is an error.
+fn resolve_monitor(requested: String, declared: Option<String>) -> Result<String, RunnerError> {
+    match declared {
+        None => Ok(requested),
+        Some(declared) if requested == NOMONITOR_INDICATOR || requested == declared => Ok(declared),
+        Some(declared) => Err(RunnerError::MonitorConflict(declared, requested)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restart_backoff() {
+        let delays: Vec<u64> = (0..4).map(|n| restart_delay(n).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8]);
+        // Large counts saturate instead of overflowing
+        assert!(restart_delay(64) > restart_delay(3));
+    }
+
+    #[test]
+    fn monitor_precedence() {
+        assert_eq!(
+            resolve_monitor("DP-1".to_string(), None),
+            Ok("DP-1".to_string())
+        );
+        assert_eq!(
+            resolve_monitor(NOMONITOR_INDICATOR.to_string(), Some("eDP-1".to_string())),
+            Ok("eDP-1".to_string())
+        );
+        assert_eq!(
+            resolve_monitor("eDP-1".to_string(), Some("eDP-1".to_string())),
+            Ok("eDP-1".to_string())
+        );
+        assert_eq!(
+            resolve_monitor("DP-1".to_string(), Some("eDP-1".to_string())),
+            Err(RunnerError::MonitorConflict(
+                "eDP-1".to_string(),
+                "DP-1".to_string()
+            ))
+        );
+    }
+}
@@ -1,32 +1,62 @@
 mod actions;
 mod commands;
 mod exec;
+mod frame;
 mod imp;
 
-pub use actions::Action;
-pub use commands::{CmdDuration, Command};
+pub use actions::{Action, PauseMode};
+pub use commands::{
+    Block, CmdDuration, Command, Criterion, Line, Order, Target, parse_weighted_line,
+};
+pub use imp::dry_run;
 
+use chrono::{Local, NaiveTime};
 use smol::channel::{Receiver, Sender, TrySendError};
 use smol::lock::Mutex;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
-use std::path::PathBuf;
+use std::fs::File;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use thiserror::Error;
 
 use crate::backend::Backend;
-use crate::utils::state::save_state;
+use crate::daemon::WORKSHOP;
+use crate::utils::clock;
+use crate::utils::output::EngineLog;
+use crate::utils::playlist::{self, Playlist};
+use crate::utils::state::{SavedState, save_state};
+use crate::utils::workshop::Library;
 use exec::ExecInfo;
+use frame::{DirEntries, Frame};
 
 /// The special monitor name to indicate this runner has no associated monitor.
 pub const NOMONITOR_INDICATOR: &str = "NOMONITOR";
 
+/// How many slots in a row a wallpaper may keep crashing in before it is skipped.
+const FAILED_SLOTS: usize = 2;
+
 pub struct RunnerHandle {
-    index: usize,
-    commands: Vec<Command>,
+    /// Playlists being executed, the first one is the loaded playlist and is never popped.
+    /// [`Command::Call`] pushes the called playlist on top.
+    stack: Vec<Frame>,
     state: State,
-
-    path: PathBuf,
+    /// The monitor this runner operates on, or [`NOMONITOR_INDICATOR`].
+    monitor: String,
+    rng: fastrand::Rng,
+    /// State of `rng` when the current command of the loaded playlist began.
+    seed: u64,
+    /// How `linux-wallpaperengine` exited the last time it crashed.
+    last_crash: Option<String>,
+    /// Wallpapers that kept crashing, which are skipped until the daemon restarts.
+    bad: HashSet<String>,
+    /// How many slots in a row each wallpaper has kept crashing in.
+    failures: HashMap<String, usize>,
+    /// Times the loaded playlist has gone back to its beginning.
+    passes: usize,
+    /// Output of `linux-wallpaperengine`.
+    logs: EngineLog,
 
     tx: Sender<Action>,
 }
@@ -36,6 +66,20 @@ pub struct Runner {
     internal: Arc<Mutex<RunnerHandle>>,
     backend: Backend,
     rx: Receiver<Action>,
+    /// The last wallpaper shown, which `stop` keeps showing.
+    last: Option<Command>,
+    /// Whether anything has been waited for since the loaded playlist went back to its beginning.
+    shown: bool,
+    /// Set for a dry run, see [`dry_run`].
+    dry: Option<DryRun>,
+}
+
+/// What a dry run has gone through so far.
+struct DryRun {
+    /// Wallpapers and sleeps in the order they would be shown.
+    shown: Vec<String>,
+    /// How many entries to go through before exiting.
+    limit: usize,
 }
 
 impl RunnerHandle {
@@ -47,42 +91,413 @@ impl RunnerHandle {
         self.tx.try_send(action)
     }
 
+    /// Returns the monitor this [`Runner`] operates on, or [`NOMONITOR_INDICATOR`].
+    pub fn monitor(&self) -> &str {
+        &self.monitor
+    }
+
     /// Returns whether this [`Runner`] has exited.
     pub fn exited(&self) -> bool {
-        matches!(self.state, State::Exited)
+        matches!(self.state, State::Exited | State::Unloaded)
+    }
+
+    /// Returns whether this [`Runner`] is paused.
+    pub fn paused(&self) -> bool {
+        matches!(self.state, State::Paused(_) | State::Frozen(_))
+    }
+
+    /// Returns the output of `linux-wallpaperengine` for this [`Runner`].
+    pub fn logs(&self) -> EngineLog {
+        self.logs.clone()
+    }
+
+    /// Describes the state of this [`Runner`], such as why it has exited.
+    pub fn status(&self) -> String {
+        self.state.to_string()
+    }
+
+    /// The playlist currently being executed.
+    fn frame(&self) -> &Frame {
+        self.stack.last().expect("Runner should have a playlist")
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.stack
+            .last_mut()
+            .expect("Runner should have a playlist")
+    }
+
+    /// Fetches the current [`Command`].
+    ///
+    /// At the end of a called playlist or a block, returns to the command after it.
+    /// A `between` block is also left once its end time is reached.
+    /// At the end of the loaded playlist, goes back to the beginning,
+    /// and a shuffled playlist is shuffled again.
+    fn fetch(&mut self) -> Option<Command> {
+        let now = Local::now().time();
+        while self.frame().index >= self.frame().len() || !self.frame().open_at(now) {
+            if self.stack.len() > 1 {
+                self.stack.pop();
+                self.frame_mut().index += 1;
+            } else {
+                let frame = &mut self.stack[0];
+                frame.index = 0;
+                if frame.order.is_some() {
+                    frame.shuffle(&mut self.rng);
+                }
+                self.passes += 1;
+            }
+        }
+        if self.stack.len() == 1 {
+            self.seed = self.rng.get_seed();
+        }
+        self.frame().current()
+    }
+
+    /// Enters a block, running the given commands before returning to the command after it.
+    fn enter_block(&mut self, name: &'static str, commands: Vec<Command>) {
+        let path = self.frame().path.clone();
+        self.stack.push(Frame {
+            block: Some(name),
+            ..Frame::new(path, commands, 0)
+        });
+    }
+
+    /// Enters a `dir`, playing each wallpaper found in the directory by
+    /// [`crate::utils::workshop::scan`].
+    fn enter_dir(
+        &mut self,
+        path: &Path,
+        mut found: Vec<(PathBuf, SystemTime)>,
+        duration: CmdDuration,
+        order: Order,
+        props: HashMap<String, String>,
+    ) {
+        match order {
+            Order::Name => found.sort_by(|(a, _), (b, _)| a.cmp(b)),
+            Order::Mtime => found.sort_by_key(|(_, modified)| *modified),
+            Order::Random => self.rng.shuffle(&mut found),
+        }
+        if found.is_empty() {
+            log::warn!("No wallpapers in `{}`", path.to_string_lossy());
+        }
+        self.enter_block("dir", vec![]);
+        self.frame_mut().dir = Some(DirEntries {
+            paths: found.into_iter().map(|(dir, _)| dir).collect(),
+            duration,
+            props: props
+                .into_iter()
+                .map(|(key, value)| (key, Some(value)))
+                .collect(),
+        });
+    }
+
+    /// Enters a `{ ... }` block, which starts with the current default properties and restores
+    /// them once left.
+    fn enter_scope(&mut self, commands: Vec<Command>) {
+        let defaults = self.defaults();
+        self.enter_block("scope", commands);
+        self.frame_mut().defaults = Some(defaults);
+    }
+
+    /// Default properties of the innermost `{ ... }` block, or of the whole runner.
+    fn defaults(&self) -> HashMap<String, String> {
+        self.stack
+            .iter()
+            .rev()
+            .find_map(|frame| frame.defaults.clone())
+            .unwrap_or_default()
+    }
+
+    /// Same as [`RunnerHandle::defaults`], but mutable.
+    fn defaults_mut(&mut self) -> &mut HashMap<String, String> {
+        let index = self
+            .stack
+            .iter()
+            .rposition(|frame| frame.defaults.is_some())
+            .unwrap_or(0);
+        self.stack[index].defaults.get_or_insert_default()
+    }
+
+    /// Enters a `between` block, which is left at the end time.
+    fn enter_window(&mut self, start: NaiveTime, end: NaiveTime, commands: Vec<Command>) {
+        self.enter_block("between", commands);
+        self.frame_mut().window = Some((start, end));
+    }
+
+    /// How long until the first of the `between` blocks being executed ends.
+    ///
+    /// Executions are cut short to this, so that the block is left on time.
+    fn time_left(&self) -> Option<Duration> {
+        let now = Local::now();
+        self.stack
+            .iter()
+            .filter_map(|frame| frame.window)
+            .map(|(_, end)| clock::time_until(&now, end))
+            .min()
+    }
+
+    /// Picks a wallpaper of a [`Command::Query`] at random among those matching all criteria.
+    fn query(&mut self, library: &Library, criteria: &[Criterion]) -> Option<String> {
+        let found =
+            library.find(|project| criteria.iter().all(|criterion| criterion.matches(project)));
+        if found.is_empty() {
+            return None;
+        }
+        Some(found[self.rng.usize(..found.len())].to_string())
+    }
+
+    /// Picks entries of a [`Command::Choose`] at random.
+    ///
+    /// Entries with a larger weight are more likely to be picked, and each is picked once at most.
+    fn pick(&mut self, count: usize, mut entries: Vec<(u32, Command)>) -> Vec<Command> {
+        let mut picked = vec![];
+        while picked.len() < count {
+            let total: u64 = entries.iter().map(|(weight, _)| u64::from(*weight)).sum();
+            if total == 0 {
+                break;
+            }
+            let mut roll = self.rng.u64(0..total);
+            let Some(index) = entries.iter().position(|(weight, _)| {
+                if roll < u64::from(*weight) {
+                    true
+                } else {
+                    roll -= u64::from(*weight);
+                    false
+                }
+            }) else {
+                break;
+            };
+            picked.push(entries.remove(index).1);
+        }
+        picked
+    }
+
+    /// Decides whether the `goto` at the current index should jump.
+    ///
+    /// Each bounded `goto` keeps its own counter, which is reset once it falls through,
+    /// so that nested loops repeat as a whole.
+    fn take_goto(&mut self, times: Option<usize>) -> bool {
+        let Some(times) = times else {
+            return true;
+        };
+        let frame = self.frame_mut();
+        let remaining = frame.counters.entry(frame.index).or_insert(times);
+        if *remaining == 0 {
+            frame.counters.remove(&frame.index);
+            false
+        } else {
+            *remaining -= 1;
+            true
+        }
+    }
+
+    /// Performs the `goto` at the current index, jumping to `target` unless its counter has run
+    /// out.
+    ///
+    /// Returns [`None`] if it falls through, otherwise whether it starts the playlist over, which
+    /// is when it jumps backwards endlessly.
+    fn jump(&mut self, target: usize, times: Option<usize>) -> Option<bool> {
+        if !self.take_goto(times) {
+            return None;
+        }
+        let frame = self.frame_mut();
+        let backwards = target <= frame.index;
+        frame.index = target;
+        Some(times.is_none() && backwards)
+    }
+
+    /// Paths of the playlists on the stack, the innermost last.
+    fn paths(&self) -> Vec<PathBuf> {
+        self.stack.iter().map(|frame| frame.path.clone()).collect()
+    }
+
+    /// Enters a called playlist, which is read by [`read_called`].
+    fn call(&mut self, path: PathBuf, parsed: Playlist) {
+        // Called playlists run on the caller's monitor
+        let mut frame = Frame::new(path, parsed.commands, 0);
+        if parsed.shuffle {
+            frame.shuffle(&mut self.rng);
+        }
+        self.stack.push(frame);
+    }
+
+    /// Goes back to the beginning of the current playlist or block, which is shuffled again if
+    /// it's shuffled.
+    fn restart(&mut self) {
+        let frame = self
+            .stack
+            .last_mut()
+            .expect("Runner should have a playlist");
+        frame.index = 0;
+        if frame.order.is_some() {
+            frame.shuffle(&mut self.rng);
+        }
+    }
+
+    /// Records whether a wallpaper kept crashing for a whole slot, after being restarted as many
+    /// times as allowed.
+    ///
+    /// Returns whether the wallpaper is now skipped, which is once it kept crashing for
+    /// [`FAILED_SLOTS`] slots in a row.
+    fn record_slot(&mut self, id: &str, failed: bool) -> bool {
+        if !failed {
+            self.failures.remove(id);
+            return false;
+        }
+        let failures = self.failures.entry(id.to_string()).or_default();
+        *failures += 1;
+        if *failures < FAILED_SLOTS {
+            return false;
+        }
+        self.failures.remove(id);
+        self.bad.insert(id.to_string());
+        true
+    }
+
+    /// Gives wallpapers skipped for crashing another chance.
+    fn forgive(&mut self) {
+        self.bad.clear();
+        self.failures.clear();
+    }
+
+    /// Replaces the loaded playlist, and anything entered from it, with the playlist `parsed` from
+    /// `path`, see [`read_handed`].
+    ///
+    /// # Errors
+    /// If the playlist declares another monitor than the one of this runner, this will return
+    /// [`RunnerError::MonitorConflict`].
+    fn hand_over(&mut self, path: PathBuf, parsed: Playlist) -> Result<(), RunnerError> {
+        if let Some(declared) = parsed.monitor
+            && declared != self.monitor
+        {
+            return Err(RunnerError::MonitorConflict(declared, self.monitor.clone()));
+        }
+        let mut frame = Frame::new(path, parsed.commands, 0);
+        if parsed.shuffle {
+            frame.shuffle(&mut self.rng);
+        }
+        self.stack = vec![frame];
+        self.seed = self.rng.get_seed();
+        Ok(())
     }
 
     /// Saves the state of this runner for later resume.
     pub fn save(&self) {
-        if save_state(self.index, &self.path).is_err() {
+        let root = &self.stack[0];
+        let state = SavedState {
+            index: root.index,
+            seed: Some(self.seed),
+            order: root.order.clone(),
+        };
+        if save_state(&state, &root.path).is_err() {
             log::error!("Unable to save state");
         }
     }
 }
 
+/// Reads a playlist called from the playlists on `stack`, see [`RunnerHandle::paths`].
+///
+/// This does blocking IO, so it's done without holding the lock on [`RunnerHandle`].
+///
+/// # Errors
+/// If the playlist is already on the stack, or cannot be parsed, this will return
+/// [`RunnerError::CallFailed`].
+fn read_called(path: &Path, stack: &[PathBuf]) -> Result<(PathBuf, Playlist), RunnerError> {
+    let from = stack.last().expect("Runner should have a playlist");
+    let found = playlist::find_from(path, from).map_err(|err| {
+        log::error!("{err}");
+        RunnerError::CallFailed
+    })?;
+    let identity = playlist::identify(&found);
+    if stack
+        .iter()
+        .any(|path| playlist::identify(path) == identity)
+    {
+        log::error!("`{}` calls itself", path.to_string_lossy());
+        return Err(RunnerError::CallFailed);
+    }
+    let file = File::open(&found).map_err(|err| {
+        log::error!("{err}");
+        RunnerError::CallFailed
+    })?;
+    let parsed = playlist::parse(&found, &file).ok_or(RunnerError::CallFailed)?;
+    Ok((found, parsed))
+}
+
+/// Reads a playlist handed over to by `then` in the playlist `from`.
+///
+/// Like [`read_called`], this does blocking IO without holding the lock on [`RunnerHandle`].
+///
+/// # Errors
+/// If the playlist cannot be parsed, this will return [`RunnerError::HandOverFailed`].
+fn read_handed(path: &Path, from: &Path) -> Result<(PathBuf, Playlist), RunnerError> {
+    let found = playlist::find_from(path, from).map_err(|err| {
+        log::error!("{err}");
+        RunnerError::HandOverFailed
+    })?;
+    let file = File::open(&found).map_err(|err| {
+        log::error!("{err}");
+        RunnerError::HandOverFailed
+    })?;
+    let parsed = playlist::parse(&found, &file).ok_or(RunnerError::HandOverFailed)?;
+    Ok((found, parsed))
+}
+
 impl Display for RunnerHandle {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}\n{} - Index {}",
-            self.state,
-            self.path.to_string_lossy(),
-            self.index
-        )
+        write!(f, "{}", self.state)?;
+        if let Some(crash) = &self.last_crash {
+            write!(f, "\nLast crash: {crash}")?;
+        }
+        if !self.bad.is_empty() {
+            let mut bad: Vec<_> = self.bad.iter().map(String::as_str).collect();
+            bad.sort_unstable();
+            write!(f, "\nSkipped for crashing: {}", bad.join(", "))?;
+        }
+        for (depth, frame) in self.stack.iter().enumerate() {
+            let indent = "  ".repeat(depth);
+            let arrow = if depth == 0 { "" } else { "-> " };
+            if let Some(block) = frame.block {
+                write!(f, "\n{indent}{arrow}{block} block - Index {}", frame.index)?;
+            } else {
+                write!(
+                    f,
+                    "\n{indent}{arrow}{} - Index {}",
+                    frame.path.to_string_lossy(),
+                    frame.index
+                )?;
+            }
+            if frame.order.is_some() {
+                write!(f, " (shuffled to {})", frame.current_index())?;
+            }
+            if let Some((_, end)) = frame.window {
+                write!(f, " (until {})", end.format("%H:%M"))?;
+            }
+            for (index, remaining) in &frame.counters {
+                write!(
+                    f,
+                    "\n{indent}Goto at index {index} - {remaining} jumps remaining"
+                )?;
+            }
+        }
+        Ok(())
     }
 }
 
 impl Runner {
     async fn next(&self) {
-        self.internal.lock().await.index += 1;
+        self.internal.lock().await.frame_mut().index += 1;
     }
 
     async fn prev(&self) {
-        self.internal.lock().await.index -= 1;
+        let mut internal = self.internal.lock().await;
+        let frame = internal.frame_mut();
+        frame.index = frame.index.saturating_sub(1);
     }
 
     async fn goto(&self, index: usize) {
-        self.internal.lock().await.index = index;
+        self.internal.lock().await.frame_mut().index = index;
     }
 
     async fn update_state(&self, state: State) {
@@ -94,12 +509,20 @@ impl Runner {
 pub enum RunnerError {
     #[error("Runner init failed")]
     InitFailed,
-    #[error("Cannot spawn `linux-wallpaperengine`")]
-    CannotSpawn,
-    #[error("`linux-wallpaperengine` unexpectedly exited")]
-    EngineDied,
+    #[error("Cannot spawn `linux-wallpaperengine`: {0}")]
+    CannotSpawn(String),
+    #[error("`linux-wallpaperengine` {0}")]
+    EngineDied(String),
     #[error("Failed to cleanup")]
     CleanupFail,
+    #[error("Cannot call playlist")]
+    CallFailed,
+    #[error("Cannot hand over to playlist")]
+    HandOverFailed,
+    #[error("Cannot send signal to `linux-wallpaperengine`")]
+    SignalFailed,
+    #[error("Playlist is meant for monitor {0}, but {1} is requested")]
+    MonitorConflict(String, String),
 }
 
 /// Runner's state
@@ -108,7 +531,26 @@ enum State {
     Ready,
     Running(ExecInfo),
     Paused(Option<Duration>),
+    /// Paused with `linux-wallpaperengine` stopped by SIGSTOP.
+    Frozen(Option<Duration>),
+    /// Stopped by `stop`, still showing the last wallpaper.
+    Stopped(ExecInfo),
+    /// `linux-wallpaperengine` cannot be spawned, waits for the rest of the entry with the reason.
+    Failed(String),
     Exited,
+    /// Unloaded by `unload`.
+    Unloaded,
+}
+
+/// Describes the wallpaper of an execution, with its title if it's installed from the workshop.
+fn describe(info: &ExecInfo) -> String {
+    match &info.wallpaper {
+        Some(id) => match WORKSHOP.read_blocking().title(id) {
+            Some(title) => format!(" `{id}` ({title})"),
+            None => format!(" `{id}`"),
+        },
+        None => String::new(),
+    }
 }
 
 impl Display for State {
@@ -117,18 +559,323 @@ impl Display for State {
             State::Ready => "Ready",
             // TODO: Use humantime for formatting
             State::Running(info) => {
+                let shown = describe(info);
                 if let Some(duration) = info.duration {
                     &format!(
-                        "Running - expected to take {:?} - started at {:?}",
+                        "Running{shown} - expected to take {:?} - started at {:?}",
                         duration, info.start
                     )
                 } else {
-                    &format!("Running - started at {:?}", info.start)
+                    &format!("Running{shown} - started at {:?}", info.start)
+                }
+            }
+            State::Paused(remaining) => {
+                if let Some(remaining) = remaining {
+                    &format!("Paused - {remaining:?} remaining")
+                } else {
+                    "Paused"
                 }
             }
-            State::Paused(_) => "Paused",
+            State::Frozen(remaining) => {
+                if let Some(remaining) = remaining {
+                    &format!("Frozen - {remaining:?} remaining")
+                } else {
+                    "Frozen"
+                }
+            }
+            State::Stopped(info) => {
+                &format!("Stopped at the end of the playlist{}", describe(info))
+            }
+            State::Failed(reason) => &format!("Failed - {reason}"),
             State::Exited => "Exited",
+            State::Unloaded => "Unloaded by the playlist",
         };
         write!(f, "{string}")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::workshop;
+
+    /// A runner of the given commands, whose random picks are always the same.
+    fn handle(commands: Vec<Command>) -> RunnerHandle {
+        let (tx, _rx) = smol::channel::unbounded();
+        RunnerHandle {
+            stack: vec![Frame::new(PathBuf::new(), commands, 0)],
+            state: State::Ready,
+            monitor: NOMONITOR_INDICATOR.to_string(),
+            rng: fastrand::Rng::with_seed(0),
+            seed: 0,
+            last_crash: None,
+            bad: HashSet::new(),
+            failures: HashMap::new(),
+            passes: 0,
+            logs: EngineLog::default(),
+            tx,
+        }
+    }
+
+    #[test]
+    fn bounded_goto() {
+        // Same layout as `playlists/goto.playlist`
+        let commands = vec![
+            Command::Wallpaper("1".to_string(), CmdDuration::Infinite, Default::default()),
+            Command::Wallpaper("2".to_string(), CmdDuration::Infinite, Default::default()),
+            Command::Goto(Target::Index(0), Some(2)),
+            Command::Wallpaper("3".to_string(), CmdDuration::Infinite, Default::default()),
+            Command::Goto(Target::Index(1), Some(1)),
+            Command::Wallpaper("4".to_string(), CmdDuration::Infinite, Default::default()),
+            Command::Wallpaper("5".to_string(), CmdDuration::Infinite, Default::default()),
+            Command::Wallpaper("6".to_string(), CmdDuration::Infinite, Default::default()),
+            Command::Goto(Target::Index(6), Some(2)),
+            Command::End,
+        ];
+        let mut handle = handle(commands);
+
+        // Wallpapers are shown, then the runner moves on, as in `Runner::run`
+        let mut shown = vec![];
+        loop {
+            match handle.fetch() {
+                Some(Command::Wallpaper(id, _, _)) => shown.push(id),
+                Some(Command::Goto(Target::Index(target), times)) => {
+                    if let Some(started_over) = handle.jump(target, times) {
+                        assert!(!started_over);
+                        continue;
+                    }
+                }
+                _ => break,
+            }
+            handle.frame_mut().index += 1;
+        }
+        assert_eq!(
+            shown,
+            [
+                "1", "2", "1", "2", "1", "2", "3", "2", "1", "2", "1", "2", "3", "4", "5", "6",
+                "5", "6", "5", "6"
+            ]
+        );
+        assert!(handle.frame().counters.is_empty());
+
+        // An endless jump backwards starts the playlist over
+        handle.frame_mut().index = 8;
+        assert_eq!(handle.jump(6, None), Some(true));
+        assert_eq!(handle.jump(9, None), Some(false));
+    }
+
+    #[test]
+    fn call_stack() {
+        let mut handle = handle(vec![
+            Command::Sleep(CmdDuration::Infinite),
+            Command::Call(PathBuf::from("called.playlist")),
+            Command::End,
+        ]);
+        handle.frame_mut().path = PathBuf::from("root.playlist");
+        handle.frame_mut().index = 1;
+        handle.stack.push(Frame::new(
+            PathBuf::from("called.playlist"),
+            vec![Command::Label("called".to_string())],
+            0,
+        ));
+        assert_eq!(handle.fetch(), Some(Command::Label("called".to_string())));
+        assert_eq!(
+            handle.to_string(),
+            "Ready\nroot.playlist - Index 1\n  -> called.playlist - Index 0"
+        );
+
+        handle.frame_mut().index += 1;
+        assert_eq!(handle.fetch(), Some(Command::End));
+        assert_eq!(handle.stack.len(), 1);
+
+        handle.frame_mut().index += 1;
+        assert_eq!(handle.fetch(), Some(Command::Sleep(CmdDuration::Infinite)));
+
+        let stack = [PathBuf::from("../playlists/include.playlist")];
+        let (found, parsed) = read_called(Path::new("intermission"), &stack).unwrap();
+        assert_eq!(found, PathBuf::from("../playlists/intermission.playlist"));
+        handle.call(found, parsed);
+        assert_eq!(handle.stack.len(), 2);
+        assert_eq!(
+            read_called(Path::new("include"), &stack),
+            Err(RunnerError::CallFailed)
+        );
+    }
+
+    #[test]
+    fn random_pick() {
+        let mut handle = handle(vec![Command::End]);
+        let entries = vec![
+            (1, Command::Label("a".to_string())),
+            (0, Command::Label("never".to_string())),
+            (3, Command::Label("b".to_string())),
+        ];
+
+        let picked = handle.pick(1, entries.clone());
+        assert_eq!(picked.len(), 1);
+        let picked = handle.pick(5, entries.clone());
+        assert_eq!(picked.len(), 2);
+        assert!(!picked.contains(&Command::Label("never".to_string())));
+
+        // Same seed, same picks
+        handle.fetch();
+        let seed = handle.seed;
+        let first = handle.pick(1, entries.clone());
+        handle.rng = fastrand::Rng::with_seed(seed);
+        assert_eq!(handle.pick(1, entries), first);
+
+        handle.enter_block("random", first);
+        assert_eq!(
+            handle.to_string(),
+            "Ready\n - Index 0\n  -> random block - Index 0"
+        );
+    }
+
+    #[test]
+    fn dir_entries() {
+        let mut handle = handle(vec![Command::End]);
+        let dir = Path::new("../playlists/workshop");
+        let duration = CmdDuration::Finite(Duration::from_secs(1));
+        let found = workshop::scan(dir).unwrap();
+        let props = HashMap::from([("fps".to_string(), "30".to_string())]);
+        handle.enter_dir(dir, found, duration.clone(), Order::Name, props);
+        assert_eq!(handle.stack.len(), 2);
+        let wallpaper = |id: &str| {
+            Command::Wallpaper(
+                id.to_string(),
+                duration.clone(),
+                HashMap::from([("fps".to_string(), Some("30".to_string()))]),
+            )
+        };
+        assert_eq!(
+            handle.fetch(),
+            Some(wallpaper("../playlists/workshop/1001"))
+        );
+        handle.frame_mut().index += 1;
+        assert_eq!(
+            handle.fetch(),
+            Some(wallpaper("../playlists/workshop/1002"))
+        );
+        // Back to the playlist once every wallpaper is played
+        handle.frame_mut().index += 1;
+        assert_eq!(handle.fetch(), Some(Command::End));
+        assert_eq!(handle.stack.len(), 1);
+    }
+
+    #[test]
+    fn query_library() {
+        let mut handle = handle(vec![Command::End]);
+        let library = Library::index(Path::new("../playlists/workshop")).unwrap();
+        let nature = [
+            Criterion::Tag("nature".to_string()),
+            Criterion::Type("scene".to_string()),
+        ];
+        assert_eq!(handle.query(&library, &nature), Some("1001".to_string()));
+        assert_eq!(
+            handle.query(&library, &[Criterion::Title("night".to_string())]),
+            Some("1002".to_string())
+        );
+        assert_eq!(
+            handle.query(&library, &[Criterion::Tag("Anime".to_string())]),
+            None
+        );
+        for _ in 0..10 {
+            let picked = handle.query(&library, &[]).unwrap();
+            assert!(picked == "1001" || picked == "1002");
+        }
+    }
+
+    #[test]
+    fn scoped_defaults() {
+        let mut handle = handle(vec![Command::End]);
+        let fps = || HashMap::from([("fps".to_string(), "30".to_string())]);
+        *handle.defaults_mut() = fps();
+
+        handle.enter_scope(vec![Command::Sleep(CmdDuration::Infinite)]);
+        assert_eq!(handle.defaults(), fps());
+        handle.defaults_mut().clear();
+        // Blocks other than `{ ... }` share the defaults of the outer one
+        handle.enter_block("random", vec![Command::Sleep(CmdDuration::Infinite)]);
+        handle
+            .defaults_mut()
+            .insert("volume".to_string(), "0".to_string());
+        assert_eq!(
+            handle.defaults(),
+            HashMap::from([("volume".to_string(), "0".to_string())])
+        );
+
+        handle.frame_mut().index = 1;
+        handle.fetch();
+        handle.frame_mut().index = 1;
+        assert_eq!(handle.fetch(), Some(Command::End));
+        assert_eq!(handle.defaults(), fps());
+    }
+
+    #[test]
+    fn crash_policy() {
+        let mut handle = handle(vec![Command::Sleep(CmdDuration::Infinite)]);
+        // A slot shown without crashing clears the failures before it
+        assert!(!handle.record_slot("1", true));
+        assert!(!handle.record_slot("1", false));
+        assert!(!handle.record_slot("1", true));
+        assert!(handle.bad.is_empty());
+        assert!(handle.record_slot("1", true));
+        assert!(handle.bad.contains("1"));
+        // Failures are counted for each wallpaper
+        assert!(!handle.record_slot("2", true));
+        handle.forgive();
+        assert!(handle.bad.is_empty());
+        assert!(!handle.record_slot("2", true));
+
+        // Going back to the beginning counts as a pass
+        assert_eq!(handle.fetch(), Some(Command::Sleep(CmdDuration::Infinite)));
+        assert_eq!(handle.passes, 0);
+        handle.frame_mut().index += 1;
+        handle.fetch();
+        assert_eq!(handle.passes, 1);
+    }
+
+    #[test]
+    fn end_behaviours() {
+        let mut handle = handle(vec![
+            Command::Sleep(CmdDuration::Infinite),
+            Command::Loop(Some(1)),
+            Command::Then(PathBuf::from("../playlists/random.playlist")),
+        ]);
+        handle.frame_mut().path = PathBuf::from("root.playlist");
+        handle.frame_mut().index = 1;
+        // Loops once, then falls through
+        assert!(handle.take_goto(Some(1)));
+        handle.restart();
+        assert_eq!(handle.frame().index, 0);
+        handle.frame_mut().index = 1;
+        assert!(!handle.take_goto(Some(1)));
+
+        // Found beside the playlist handing over
+        let from = Path::new("../playlists/default.playlist");
+        let (found, parsed) = read_handed(Path::new("random.playlist"), from).unwrap();
+        handle.hand_over(found, parsed).unwrap();
+        assert_eq!(handle.stack.len(), 1);
+        assert_eq!(
+            handle.frame().path,
+            PathBuf::from("../playlists/random.playlist")
+        );
+        assert_eq!(handle.frame().index, 0);
+        let (found, parsed) = read_handed(Path::new("test"), from).unwrap();
+        assert_eq!(
+            handle.hand_over(found, parsed),
+            Err(RunnerError::MonitorConflict(
+                "eDP-1".to_string(),
+                NOMONITOR_INDICATOR.to_string()
+            ))
+        );
+        assert_eq!(
+            read_handed(Path::new("nowhere.playlist"), from),
+            Err(RunnerError::HandOverFailed)
+        );
+
+        handle.state = State::Unloaded;
+        assert!(handle.exited());
+        assert_eq!(handle.status(), "Unloaded by the playlist");
+    }
+}
@@ -0,0 +1,269 @@
+//! Computes durations from the wall clock.
+
+use chrono::{
+    DateTime, LocalResult, NaiveDateTime, NaiveTime, Offset, TimeDelta, TimeZone, Timelike,
+};
+use std::time::Duration;
+
+/// Seconds in a day on the clock.
+const DAY: u64 = 24 * 60 * 60;
+
+/// Gets how long it takes from `now` to the next time the clock shows `time`.
+///
+/// If the clock shows `time` right now, this is a whole day.
+/// If `time` is skipped by a DST change on that day, the first moment after the gap is used.
+/// If `time` is shown twice because of a DST change, the first one that is still to come is used.
+pub fn time_until<Tz: TimeZone>(now: &DateTime<Tz>, time: NaiveTime) -> Duration {
+    let timezone = now.timezone();
+    let today = now.date_naive();
+    // Yesterday as well, in case the clock has just been turned back across midnight
+    let target = [today.pred_opt(), Some(today), today.succ_opt()]
+        .into_iter()
+        .flatten()
+        .flat_map(|date| instants(&timezone, date.and_time(time)))
+        .filter(|target| target > now)
+        .min();
+    match target {
+        Some(target) => (target - now.clone()).to_std().unwrap_or_default(),
+        None => Duration::from_secs(DAY),
+    }
+}
+
+/// Gets the moments a local time maps to, both of them if it is shown twice.
+///
+/// If the local time is skipped, this is the first moment after the gap.
+fn instants<Tz: TimeZone>(timezone: &Tz, naive: NaiveDateTime) -> Vec<DateTime<Tz>> {
+    match timezone.from_local_datetime(&naive) {
+        LocalResult::Single(target) => vec![target],
+        LocalResult::Ambiguous(first, second) => vec![first, second],
+        LocalResult::None => {
+            // Gaps start on a whole minute, and are never longer than a few hours
+            let minute = naive
+                .with_second(0)
+                .and_then(|naive| naive.with_nanosecond(0));
+            (1..=3 * 60)
+                .filter_map(|step| {
+                    let naive = minute? + TimeDelta::minutes(step);
+                    timezone.from_local_datetime(&naive).earliest()
+                })
+                .take(1)
+                .collect()
+        }
+    }
+}
+
+/// Gets how long it takes from `now` to the next multiple of `period` on the clock, counted from
+/// midnight, such as the next quarter hour for 15 minutes.
+///
+/// If `period` does not divide a day, the last period of a day is cut short at midnight.
+/// When the clock jumps because of a DST change, the next multiple is counted from where it lands.
+pub fn time_to_boundary<Tz: TimeZone>(now: &DateTime<Tz>, period: Duration) -> Duration {
+    let period = period.as_secs().max(1);
+    let mut from = now.clone();
+    // Only the moment the clock lands on after a jump may be a boundary itself
+    let mut landed = false;
+    // Each round steps over one DST change, of which there are never many in a day
+    for _ in 0..4 {
+        let elapsed = u64::from(from.num_seconds_from_midnight());
+        let nanos = from.nanosecond();
+        let next = if landed && elapsed % period == 0 && nanos == 0 {
+            elapsed
+        } else {
+            ((elapsed / period + 1) * period).min(DAY)
+        };
+        let step = TimeDelta::seconds(i64::try_from(next - elapsed).unwrap_or_default())
+            - TimeDelta::nanoseconds(i64::from(nanos));
+        let target = from.clone() + step;
+        if target.offset().fix() == from.offset().fix() {
+            return (target - now.clone()).to_std().unwrap_or_default();
+        }
+        from = jump(from, target);
+        landed = true;
+    }
+    time_until(now, NaiveTime::MIN)
+}
+
+/// Finds the first moment after `from`, and no later than `to`, with another UTC offset than
+/// `from`, which is where the clock lands after a DST change.
+fn jump<Tz: TimeZone>(from: DateTime<Tz>, to: DateTime<Tz>) -> DateTime<Tz> {
+    let offset = from.offset().fix();
+    let (mut before, mut after) = (from, to);
+    while after.clone() - before.clone() > TimeDelta::seconds(1) {
+        let middle = before.clone() + (after.clone() - before.clone()) / 2;
+        if middle.offset().fix() == offset {
+            before = middle;
+        } else {
+            after = middle;
+        }
+    }
+    // Changes happen on a whole second
+    let late = after.nanosecond();
+    after - TimeDelta::nanoseconds(i64::from(late))
+}
+
+/// Checks whether `now` is in the window from `start` to `end`.
+///
+/// The window may cross midnight when `end` is earlier than `start`.
+/// If they are the same, the window is the whole day.
+pub fn in_window(now: NaiveTime, start: NaiveTime, end: NaiveTime) -> bool {
+    match start.cmp(&end) {
+        std::cmp::Ordering::Less => start <= now && now < end,
+        std::cmp::Ordering::Greater => start <= now || now < end,
+        std::cmp::Ordering::Equal => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, NaiveDate, Utc};
+
+    fn time(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn until_time() {
+        let now = Utc.with_ymd_and_hms(2025, 3, 1, 17, 30, 0).unwrap();
+        assert_eq!(time_until(&now, time(18, 0)), Duration::from_secs(30 * 60));
+        // Across midnight
+        assert_eq!(
+            time_until(&now, time(7, 30)),
+            Duration::from_secs(14 * 60 * 60)
+        );
+        assert_eq!(
+            time_until(&now, time(17, 30)),
+            Duration::from_secs(24 * 60 * 60)
+        );
+
+        let offset = FixedOffset::east_opt(8 * 60 * 60).unwrap();
+        let now = offset.with_ymd_and_hms(2025, 3, 1, 23, 0, 0).unwrap();
+        assert_eq!(
+            time_until(&now, time(1, 0)),
+            Duration::from_secs(2 * 60 * 60)
+        );
+    }
+
+    #[test]
+    fn boundary() {
+        let quarter = Duration::from_secs(15 * 60);
+        let now = Utc.with_ymd_and_hms(2025, 3, 1, 17, 32, 10).unwrap();
+        assert_eq!(
+            time_to_boundary(&now, quarter),
+            Duration::from_secs(12 * 60 + 50)
+        );
+        // Exactly on a boundary, the whole period is left
+        let now = Utc.with_ymd_and_hms(2025, 3, 1, 17, 45, 0).unwrap();
+        assert_eq!(time_to_boundary(&now, quarter), quarter);
+        // Across midnight
+        let now = Utc.with_ymd_and_hms(2025, 3, 1, 23, 50, 0).unwrap();
+        assert_eq!(
+            time_to_boundary(&now, Duration::from_secs(60 * 60)),
+            Duration::from_secs(10 * 60)
+        );
+        // 7 hours does not divide a day, so the last period is cut short
+        let now = Utc.with_ymd_and_hms(2025, 3, 1, 22, 0, 0).unwrap();
+        assert_eq!(
+            time_to_boundary(&now, Duration::from_secs(7 * 60 * 60)),
+            Duration::from_secs(2 * 60 * 60)
+        );
+    }
+
+    /// Central European Time in 2025, so that tests don't depend on the local time zone or tzdata.
+    #[derive(Clone, Copy, Debug)]
+    struct Berlin;
+
+    impl Berlin {
+        const CET: i32 = 60 * 60;
+        const CEST: i32 = 2 * 60 * 60;
+    }
+
+    impl TimeZone for Berlin {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Self {
+            Berlin
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            // An offset applies if the moment it gives is in effect with it
+            let applies = |offset: i32| {
+                let utc = *local - TimeDelta::seconds(offset.into());
+                (self.offset_from_utc_datetime(&utc).local_minus_utc() == offset)
+                    .then(|| FixedOffset::east_opt(offset).unwrap())
+            };
+            match (applies(Self::CEST), applies(Self::CET)) {
+                (Some(cest), Some(cet)) => LocalResult::Ambiguous(cest, cet),
+                (Some(offset), None) | (None, Some(offset)) => LocalResult::Single(offset),
+                (None, None) => LocalResult::None,
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            // Both changes happen at 01:00 UTC
+            let change = |month, day| {
+                NaiveDate::from_ymd_opt(2025, month, day)
+                    .unwrap()
+                    .and_hms_opt(1, 0, 0)
+                    .unwrap()
+            };
+            let summer = change(3, 30) <= *utc && *utc < change(10, 26);
+            FixedOffset::east_opt(if summer { Self::CEST } else { Self::CET }).unwrap()
+        }
+    }
+
+    #[test]
+    fn dst_changes() {
+        let minutes = |m: u64| Duration::from_secs(m * 60);
+        let quarter = minutes(15);
+
+        // The clock shows 02:00 to 03:00 twice on 2025-10-26, first in CEST then in CET
+        let at = |h, m| match Berlin.with_ymd_and_hms(2025, 10, 26, h, m, 0) {
+            LocalResult::Ambiguous(cest, cet) => (cest, cet),
+            result => panic!("{result:?} should be ambiguous"),
+        };
+        let cest = at(2, 50).0;
+        assert_eq!(cest.offset().fix().local_minus_utc(), Berlin::CEST);
+        assert_eq!(time_to_boundary(&cest, quarter), minutes(10));
+        let (cest, cet) = at(2, 20);
+        assert_eq!(time_until(&cest, time(2, 15)), minutes(55));
+        assert_eq!(time_until(&cest, time(2, 30)), minutes(10));
+        assert_eq!(time_until(&cet, time(2, 15)), minutes(23 * 60 + 55));
+        assert_eq!(time_to_boundary(&cet, quarter), minutes(10));
+
+        // The clock skips from 02:00 CET to 03:00 CEST on 2025-03-30
+        let cet = Berlin.with_ymd_and_hms(2025, 3, 30, 1, 50, 0).unwrap();
+        assert_eq!(time_until(&cet, time(2, 30)), minutes(10));
+        assert_eq!(time_to_boundary(&cet, minutes(60)), minutes(10));
+        // The next multiple of 7 hours after 03:00 is 07:00
+        assert_eq!(
+            time_to_boundary(&cet, minutes(7 * 60)),
+            minutes(4 * 60 + 10)
+        );
+        let cest = Berlin.with_ymd_and_hms(2025, 3, 30, 3, 0, 0).unwrap();
+        assert_eq!(time_until(&cest, time(2, 30)), minutes(23 * 60 + 30));
+        assert!(matches!(
+            Berlin.with_ymd_and_hms(2025, 3, 30, 2, 30, 0),
+            LocalResult::None
+        ));
+    }
+
+    #[test]
+    fn window() {
+        assert!(in_window(time(12, 0), time(9, 0), time(17, 0)));
+        assert!(!in_window(time(17, 0), time(9, 0), time(17, 0)));
+        // Across midnight
+        assert!(in_window(time(23, 0), time(22, 0), time(6, 0)));
+        assert!(in_window(time(5, 59), time(22, 0), time(6, 0)));
+        assert!(!in_window(time(12, 0), time(22, 0), time(6, 0)));
+        assert!(in_window(time(12, 0), time(0, 0), time(0, 0)));
+    }
+}
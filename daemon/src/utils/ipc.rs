@@ -3,11 +3,12 @@
 use nom::branch::alt;
 use nom::bytes::complete::{tag, take_till1};
 use nom::character::complete::space0;
-use nom::combinator::{map, map_res};
+use nom::combinator::{cut, map, map_res};
 use nom::{Finish, IResult, Parser};
 use std::path::PathBuf;
 use std::str::FromStr;
 
+use crate::runner::PauseMode;
 use crate::utils::ParseError;
 
 /// Possible daemon commands.
@@ -17,10 +18,14 @@ use crate::utils::ParseError;
 #[derive(Debug, PartialEq)]
 pub enum IPCCmd {
     /// Load a playlist from the given path to a runner named as a given string.
+    /// `shuffle` plays the wallpapers in random order, even if the playlist does not declare so.
+    /// `strict` refuses the playlist if any line of it has an error.
     Load {
         path: PathBuf,
         monitor: String,
         resume: bool,
+        shuffle: bool,
+        strict: bool,
     },
     /// Destroys the runner with the given name, the bool argument indicates whether a
     /// resume file should *NOT* be generated.
@@ -28,18 +33,50 @@ pub enum IPCCmd {
 
     /// Resumes the given runner.
     Play { monitor: String },
-    /// Pauses the given runner, the bool argument indicates whether `linux-wallpaperengine` should
-    /// be terminated or kept.
-    Pause { clear: bool, monitor: String },
-    /// Toggles play/pause of the given runner.
-    Toggle { monitor: String },
+    /// Pauses the given runner, [`PauseMode`] indicates whether `linux-wallpaperengine` should
+    /// be kept, terminated or frozen.
+    Pause { mode: PauseMode, monitor: String },
+    /// Toggles play/pause of the given runner, `mode` is the same as in [`IPCCmd::Pause`].
+    Toggle { mode: PauseMode, monitor: String },
 
     /// Return status information.
     Status,
+    /// Returns the output of `linux-wallpaperengine` kept by the given runner,
+    /// `follow` keeps the connection open to send new output as it comes.
+    Logs { follow: bool, monitor: String },
     /// Quit `lxwengd`
     Quit,
 }
 
+/// Parses a whitespace-separated argument.
+///
+/// Once the command name is matched, a missing argument fails the whole command instead of
+/// letting other commands try.
+fn parse_arg(input: &str) -> IResult<&str, &str> {
+    let (input, _) = space0(input)?;
+    cut(take_till1(|c: char| c.is_whitespace())).parse(input)
+}
+
+/// Same as [`parse_arg`], but the argument must be a bool.
+fn parse_bool_arg(input: &str) -> IResult<&str, bool> {
+    let (input, _) = space0(input)?;
+    cut(map_res(
+        take_till1(|c: char| c.is_whitespace()),
+        str::parse::<bool>,
+    ))
+    .parse(input)
+}
+
+/// Same as [`parse_arg`], but the argument must be a [`PauseMode`].
+fn parse_mode_arg(input: &str) -> IResult<&str, PauseMode> {
+    let (input, _) = space0(input)?;
+    cut(map_res(
+        take_till1(|c: char| c.is_whitespace()),
+        str::parse::<PauseMode>,
+    ))
+    .parse(input)
+}
+
 fn parse_quit(input: &str) -> IResult<&str, IPCCmd> {
     map(tag("quit"), |_| IPCCmd::Quit).parse(input)
 }
@@ -47,75 +84,67 @@ fn parse_status(input: &str) -> IResult<&str, IPCCmd> {
     map(tag("status"), |_| IPCCmd::Status).parse(input)
 }
 
+fn parse_logs(input: &str) -> IResult<&str, IPCCmd> {
+    let (input, _) = tag("logs")(input)?;
+    let (input, follow) = parse_bool_arg(input)?;
+    map(parse_arg, |monitor: &str| IPCCmd::Logs {
+        follow,
+        monitor: monitor.to_string(),
+    })
+    .parse(input)
+}
+
 fn parse_toggle(input: &str) -> IResult<&str, IPCCmd> {
     let (input, _) = tag("toggle")(input)?;
-    let (input, _) = space0(input)?;
-    map(take_till1(|c: char| c.is_whitespace()), |monitor: &str| {
-        IPCCmd::Toggle {
-            monitor: monitor.to_string(),
-        }
+    let (input, mode) = parse_mode_arg(input)?;
+    map(parse_arg, |monitor: &str| IPCCmd::Toggle {
+        mode,
+        monitor: monitor.to_string(),
     })
     .parse(input)
 }
 fn parse_play(input: &str) -> IResult<&str, IPCCmd> {
     let (input, _) = tag("play")(input)?;
-    let (input, _) = space0(input)?;
-    map(take_till1(|c: char| c.is_whitespace()), |monitor: &str| {
-        IPCCmd::Play {
-            monitor: monitor.to_string(),
-        }
+    map(parse_arg, |monitor: &str| IPCCmd::Play {
+        monitor: monitor.to_string(),
     })
     .parse(input)
 }
 
 fn parse_pause(input: &str) -> IResult<&str, IPCCmd> {
     let (input, _) = tag("pause")(input)?;
-    let (input, _) = space0(input)?;
-    let (input, clear) =
-        map_res(take_till1(|c: char| c.is_whitespace()), str::parse::<bool>).parse(input)?;
-    let (input, _) = space0(input)?;
-    map(take_till1(|c: char| c.is_whitespace()), |monitor: &str| {
-        IPCCmd::Pause {
-            clear,
-            monitor: monitor.to_string(),
-        }
+    let (input, mode) = parse_mode_arg(input)?;
+    map(parse_arg, |monitor: &str| IPCCmd::Pause {
+        mode,
+        monitor: monitor.to_string(),
     })
     .parse(input)
 }
 fn parse_unload(input: &str) -> IResult<&str, IPCCmd> {
     let (input, _) = tag("unload")(input)?;
-    let (input, _) = space0(input)?;
-    let (input, no_save) =
-        map_res(take_till1(|c: char| c.is_whitespace()), str::parse::<bool>).parse(input)?;
-    let (input, _) = space0(input)?;
-    map(take_till1(|c: char| c.is_whitespace()), |monitor: &str| {
-        IPCCmd::Unload {
-            no_save,
-            monitor: monitor.to_string(),
-        }
+    let (input, no_save) = parse_bool_arg(input)?;
+    map(parse_arg, |monitor: &str| IPCCmd::Unload {
+        no_save,
+        monitor: monitor.to_string(),
     })
     .parse(input)
 }
 
 fn parse_load(input: &str) -> IResult<&str, IPCCmd> {
     let (input, _) = tag("load")(input)?;
-    let (input, _) = space0(input)?;
-    let (input, path) = map_res(
-        take_till1(|c: char| c.is_whitespace()),
-        str::parse::<PathBuf>,
-    )
-    .parse(input)?;
-    let (input, _) = space0(input)?;
-    let (input, monitor) = take_till1(|c: char| c.is_whitespace())(input)?;
-    let (input, _) = space0(input)?;
-    let (input, resume) =
-        map_res(take_till1(|c: char| c.is_whitespace()), str::parse::<bool>).parse(input)?;
+    let (input, path) = parse_arg(input)?;
+    let (input, monitor) = parse_arg(input)?;
+    let (input, resume) = parse_bool_arg(input)?;
+    let (input, shuffle) = parse_bool_arg(input)?;
+    let (input, strict) = parse_bool_arg(input)?;
     Ok((
         input,
         IPCCmd::Load {
-            path,
+            path: PathBuf::from(path),
             monitor: monitor.to_string(),
             resume,
+            shuffle,
+            strict,
         },
     ))
 }
@@ -127,6 +156,7 @@ fn parse_cmd(input: &str) -> IResult<&str, IPCCmd> {
         parse_pause,
         parse_toggle,
         parse_status,
+        parse_logs,
         parse_load,
         parse_unload,
         parse_quit,
@@ -145,7 +175,7 @@ pub fn parse(input: &str) -> Result<IPCCmd, ParseError> {
         Ok((_, cmd)) => Ok(cmd),
         Err(nom::error::Error {
             input: _,
-            code: nom::error::ErrorKind::Tag,
+            code: nom::error::ErrorKind::TakeTill1,
         }) => Err(ParseError::NotEnoughArguments),
         Err(nom::error::Error {
             input: _,
@@ -169,7 +199,7 @@ mod tests {
 
     #[test]
     fn parsing_cmd() {
-        let cmd = "load /tmp/test.playlist eDP-1 false ignoredel";
+        let cmd = "load /tmp/test.playlist eDP-1 false true false";
         assert_eq!(
             parse_cmd(cmd),
             Ok((
@@ -177,7 +207,9 @@ mod tests {
                 IPCCmd::Load {
                     path: PathBuf::from("/tmp/test.playlist"),
                     monitor: "eDP-1".to_string(),
-                    resume: false
+                    resume: false,
+                    shuffle: true,
+                    strict: false
                 }
             ))
         );
@@ -205,6 +237,42 @@ mod tests {
             ))
         );
 
+        let cmd = "pause freeze DP-1";
+        assert_eq!(
+            parse_cmd(cmd),
+            Ok((
+                "",
+                IPCCmd::Pause {
+                    mode: PauseMode::Freeze,
+                    monitor: "DP-1".to_string()
+                }
+            ))
+        );
+
+        let cmd = "toggle clear eDP-1";
+        assert_eq!(
+            parse_cmd(cmd),
+            Ok((
+                "",
+                IPCCmd::Toggle {
+                    mode: PauseMode::Clear,
+                    monitor: "eDP-1".to_string()
+                }
+            ))
+        );
+
+        let cmd = "logs true DP-1";
+        assert_eq!(
+            parse_cmd(cmd),
+            Ok((
+                "",
+                IPCCmd::Logs {
+                    follow: true,
+                    monitor: "DP-1".to_string()
+                }
+            ))
+        );
+
         let cmd = "status";
         assert_eq!(parse_cmd(cmd), Ok(("", IPCCmd::Status)));
     }
@@ -213,7 +281,7 @@ mod tests {
     fn parsing_error() {
         assert_eq!(parse("play"), Err(ParseError::NotEnoughArguments));
         assert_eq!(
-            parse("sleep definitelynottime"),
+            parse("pause definitelynotamode DP-1"),
             Err(ParseError::InvalidArgument)
         );
         assert_eq!(parse("whoami"), Err(ParseError::CommandNotFound));
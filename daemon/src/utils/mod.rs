@@ -1,10 +1,15 @@
+pub mod clock;
 pub mod ipc;
+pub mod output;
 pub mod playlist;
 pub mod state;
+pub mod workshop;
 
+use std::fmt::Display;
+use std::path::Path;
 use thiserror::Error;
 
-#[derive(Debug, PartialEq, Error)]
+#[derive(Clone, Debug, PartialEq, Error)]
 pub enum ParseError {
     /// Indicates that this line is not a recognised command.
     /// Blank lines are also treated as invalid commands.
@@ -18,4 +23,107 @@ pub enum ParseError {
     /// into that type.
     #[error("Invalid arguments")]
     InvalidArgument,
+    /// The command is complete, but something other than a comment follows it.
+    #[error("Unexpected trailing input")]
+    TrailingInput,
+}
+
+/// A [`ParseError`] with where it happens in a line, and what is expected there.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub kind: ParseError,
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column, counted in characters.
+    pub column: usize,
+    /// The text at the column until the next whitespace, empty at the end of the line.
+    pub found: String,
+    /// What is expected at the column, if known.
+    pub expected: Option<&'static str>,
+    /// The whole line.
+    pub source: String,
+}
+
+impl Diagnostic {
+    /// Moves the diagnostic of a line to where the line is in a file.
+    ///
+    /// `indent` is the number of characters trimmed from the start of the line before parsing.
+    #[must_use]
+    pub fn relocate(self, line: usize, source: &str, indent: usize) -> Self {
+        Self {
+            line,
+            column: self.column + indent,
+            source: source.to_string(),
+            ..self
+        }
+    }
+
+    /// Renders the diagnostic compiler-style, quoting the line with a caret under the column.
+    pub fn render(&self, path: &Path) -> String {
+        let number = self.line.to_string();
+        let gutter = " ".repeat(number.len());
+        // Keep tabs so that the caret lines up
+        let padding: String = self
+            .source
+            .chars()
+            .take(self.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let caret = "^".repeat(self.found.chars().count().max(1));
+        format!(
+            "{}:{}:{} {}\n{gutter} |\n{number} | {}\n{gutter} | {padding}{caret}",
+            path.to_string_lossy(),
+            self.line,
+            self.column,
+            self.message(),
+            self.source
+        )
+    }
+
+    fn message(&self) -> String {
+        let found = if self.found.is_empty() {
+            String::from("end of line")
+        } else {
+            format!("`{}`", self.found)
+        };
+        match self.expected {
+            Some(expected) => format!("error: {}, found {found}, expected {expected}", self.kind),
+            None => format!("error: {}, found {found}", self.kind),
+        }
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{} {}", self.line, self.column, self.message())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_diagnostic() {
+        let diagnostic = Diagnostic {
+            kind: ParseError::InvalidArgument,
+            line: 1,
+            column: 8,
+            found: "5x".to_string(),
+            expected: Some("a duration"),
+            source: "114514 5x".to_string(),
+        }
+        .relocate(12, "\t114514 5x", 1);
+        assert_eq!(
+            diagnostic.to_string(),
+            "12:9 error: Invalid arguments, found `5x`, expected a duration"
+        );
+        assert_eq!(
+            diagnostic.render(Path::new("test.playlist")),
+            "test.playlist:12:9 error: Invalid arguments, found `5x`, expected a duration\n   \
+             |\n12 | \t114514 5x\n   | \t       ^^"
+        );
+    }
 }
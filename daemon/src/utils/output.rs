@@ -0,0 +1,139 @@
+//! Captures the output of `linux-wallpaperengine`.
+//!
+//! Each runner keeps the last lines printed by `linux-wallpaperengine` in an [`EngineLog`],
+//! which can be mirrored to a file and followed by `lxwengctl logs`.
+
+use smol::channel::{Receiver, Sender};
+use smol::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use smol::process::Child;
+use smol::stream::StreamExt;
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// How many lines an [`EngineLog`] keeps, older lines are dropped.
+pub const LOG_CAPACITY: usize = 1000;
+
+/// Output of `linux-wallpaperengine` for one runner, shared by every clone.
+#[derive(Clone, Default)]
+pub struct EngineLog {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    lines: VecDeque<String>,
+    mirror: Option<File>,
+    followers: Vec<Sender<String>>,
+}
+
+impl EngineLog {
+    /// Creates an empty log, which is also appended to the file at `mirror` if given.
+    pub fn new(mirror: Option<&Path>) -> Self {
+        let mirror = mirror.and_then(|path| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .inspect_err(|err| {
+                    log::error!("Cannot open `{}`: {err}", path.to_string_lossy());
+                })
+                .ok()
+        });
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                mirror,
+                ..Inner::default()
+            })),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner
+            .lock()
+            .expect("Engine log should not be poisoned")
+    }
+
+    /// Appends a line, dropping the oldest one once [`LOG_CAPACITY`] is reached.
+    pub fn push(&self, line: String) {
+        let mut inner = self.lock();
+        if let Some(file) = &mut inner.mirror
+            && let Err(err) = writeln!(file, "{line}")
+        {
+            log::error!("Cannot mirror engine output: {err}");
+            inner.mirror = None;
+        }
+        // Followers that have gone away are forgotten
+        inner
+            .followers
+            .retain(|follower| follower.try_send(line.clone()).is_ok());
+        if inner.lines.len() >= LOG_CAPACITY {
+            inner.lines.pop_front();
+        }
+        inner.lines.push_back(line);
+    }
+
+    /// Returns the lines kept, oldest first.
+    pub fn lines(&self) -> Vec<String> {
+        self.lock().lines.iter().cloned().collect()
+    }
+
+    /// Same as [`EngineLog::lines`], with a [`Receiver`] of the lines pushed after them.
+    pub fn follow(&self) -> (Vec<String>, Receiver<String>) {
+        let (tx, rx) = smol::channel::unbounded();
+        let mut inner = self.lock();
+        inner.followers.push(tx);
+        (inner.lines.iter().cloned().collect(), rx)
+    }
+
+    /// Takes stdout and stderr of `child`, and pushes every line they print.
+    pub fn attach(&self, child: &mut Child) {
+        if let Some(stdout) = child.stdout.take() {
+            smol::spawn(Self::pump(stdout, self.clone())).detach();
+        }
+        if let Some(stderr) = child.stderr.take() {
+            smol::spawn(Self::pump(stderr, self.clone())).detach();
+        }
+    }
+
+    /// Reads lines until the pipe is closed, which happens once the child exits.
+    async fn pump(reader: impl AsyncRead + Unpin, log: EngineLog) {
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(Ok(line)) = lines.next().await {
+            log.push(line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounded_log() {
+        let path = std::env::temp_dir().join(format!("lxwengd-test-{}.log", std::process::id()));
+        let log = EngineLog::new(Some(&path));
+        log.push(String::from("before"));
+        let (lines, rx) = log.follow();
+        assert_eq!(lines, vec!["before"]);
+
+        for i in 0..LOG_CAPACITY {
+            log.push(i.to_string());
+        }
+        let lines = log.lines();
+        assert_eq!(lines.len(), LOG_CAPACITY);
+        assert_eq!(lines[0], "0");
+        assert_eq!(rx.try_recv(), Ok(String::from("0")));
+
+        // The mirror keeps every line
+        let mirrored = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(mirrored.lines().count(), LOG_CAPACITY + 1);
+
+        drop(rx);
+        log.push(String::from("after"));
+        assert!(log.lock().followers.is_empty());
+    }
+}
@@ -1,48 +1,319 @@
 //! Finds playlist files in some given search path.
 
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
+use std::time::Duration;
 
+use crate::backend::PROPERTIES;
 use crate::daemon::SEARCH_PATH;
-use crate::runner::Command;
+use crate::runner::{Block, CmdDuration, Command, Criterion, Line, Target, parse_weighted_line};
+use crate::utils::Diagnostic;
+use crate::utils::workshop::{self, Library};
 
 /// Searches the given playlist in the given search path.
 ///
 /// # Return
-/// If `filename` is a fully qualified path to an existing file, just open that file and return it wrapped with `Ok()`. `search_path` will be ignored in this case.
+/// If `filename` is a fully qualified path to an existing file, just return it wrapped with `Ok()`. `search_path` will be ignored in this case.
 /// Otherwise, first tries to find a file relative to `search_path` with exactly the same name.
 /// Finally, tries to find a file relative to `search_path` with name `filename.playlist`.
 ///
 /// # Errors
 /// If none of these approaches can find a playlist file, returns an [`std::io::Error`].
-pub fn open(filename: &Path) -> std::io::Result<File> {
+pub fn find(filename: &Path) -> std::io::Result<PathBuf> {
     // Fully qualified path
     if filename.is_file() {
-        return File::open(filename);
+        return Ok(filename.to_path_buf());
     }
 
     // Relative to default with extension
     let mut real_path = SEARCH_PATH.to_path_buf();
     real_path.push(filename);
     if real_path.is_file() {
-        return File::open(&real_path);
+        return Ok(real_path);
     }
 
     // Relative to default without extension
-    let mut real_path = SEARCH_PATH.to_path_buf();
-    real_path.push(filename);
     let mut temp = real_path.into_os_string();
     temp.push(".playlist");
     let real_path = PathBuf::from(temp);
-    File::open(&real_path)
+    if real_path.is_file() {
+        Ok(real_path)
+    } else {
+        Err(std::io::Error::from(std::io::ErrorKind::NotFound))
+    }
+}
+
+/// Same as [`find`], but a relative `filename` is first looked up next to the playlist `from`,
+/// so that playlists can refer to those beside them wherever they are.
+///
+/// # Errors
+/// If the playlist cannot be found this way or with [`find`], returns an [`std::io::Error`].
+pub fn find_from(filename: &Path, from: &Path) -> std::io::Result<PathBuf> {
+    if filename.is_relative()
+        && let Ok(from) = find(from)
+        && let Some(dir) = from.parent()
+    {
+        let beside = dir.join(filename);
+        if beside.is_file() {
+            return Ok(beside);
+        }
+        let mut temp = beside.into_os_string();
+        temp.push(".playlist");
+        let beside = PathBuf::from(temp);
+        if beside.is_file() {
+            return Ok(beside);
+        }
+    }
+    find(filename)
+}
+
+/// Searches the given playlist in the given search path and opens it, see [`find`].
+///
+/// # Errors
+/// If the playlist cannot be found or opened, returns an [`std::io::Error`].
+pub fn open(filename: &Path) -> std::io::Result<File> {
+    File::open(find(filename)?)
+}
+
+/// Gets a unique identity of a playlist, used to detect `include` and `call` cycles.
+pub fn identify(filename: &Path) -> PathBuf {
+    find(filename)
+        .and_then(std::fs::canonicalize)
+        .unwrap_or_else(|_| filename.to_path_buf())
+}
+
+/// A parsed playlist file.
+#[derive(Debug, Default, PartialEq)]
+pub struct Playlist {
+    pub commands: Vec<Command>,
+    /// The monitor declared with the `monitor` header directive.
+    pub monitor: Option<String>,
+    /// Whether wallpapers are played in random order, declared with the `shuffle` header
+    /// directive.
+    pub shuffle: bool,
+    /// Problems found in the playlist and those it includes.
+    pub issues: Vec<Issue>,
+    /// Workshop IDs of wallpapers, with the playlist and line they are on.
+    pub wallpapers: Vec<(PathBuf, usize, String)>,
+}
+
+impl Playlist {
+    /// Whether any error is found in the playlist, even one that skips no line.
+    pub fn has_errors(&self) -> bool {
+        self.issues
+            .iter()
+            .any(|issue| issue.severity == Severity::Error)
+    }
+
+    /// Summarises how many commands are parsed and how many lines are skipped,
+    /// followed by each [`Issue`] on its own line.
+    pub fn summary(&self) -> String {
+        let skipped = self.issues.iter().filter(|issue| issue.skipped).count();
+        let mut result = format!(
+            "Parsed {} entries, skipped {skipped} lines",
+            self.commands.len()
+        );
+        for issue in &self.issues {
+            result.push('\n');
+            result.push_str(&issue.to_string());
+        }
+        result
+    }
+
+    /// Warns about wallpapers given by ID that are not in the [`Library`].
+    ///
+    /// Nothing is reported if the library is empty, as it is probably not found.
+    pub fn check_installed(&mut self, library: &Library) {
+        if library.is_empty() {
+            return;
+        }
+        for (path, line, id) in &self.wallpapers {
+            if library.get(id).is_none() {
+                let issue = Issue {
+                    path: path.clone(),
+                    line: *line,
+                    severity: Severity::Warning,
+                    message: format!("wallpaper `{id}` is not installed"),
+                    diagnostic: None,
+                    skipped: false,
+                };
+                log::warn!("{issue}");
+                self.issues.push(issue);
+            }
+        }
+        self.sort_issues();
+    }
+
+    /// Orders the issues by playlist and line, as they are found in several passes.
+    fn sort_issues(&mut self) {
+        self.issues
+            .sort_by(|a, b| a.path.cmp(&b.path).then(a.line.cmp(&b.line)));
+    }
+}
+
+/// How bad an [`Issue`] is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    /// The line is skipped, or does not take effect.
+    Error,
+    /// The line takes effect, but probably not as intended.
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A problem found in a line of a playlist.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Issue {
+    pub path: PathBuf,
+    pub line: usize,
+    pub severity: Severity,
+    pub message: String,
+    /// Where exactly the line fails to parse, for syntax errors.
+    pub diagnostic: Option<Diagnostic>,
+    /// Whether the line is left out because of this issue.
+    pub skipped: bool,
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.diagnostic {
+            Some(diagnostic) => write!(f, "{}", diagnostic.render(&self.path)),
+            None => write!(
+                f,
+                "{}:{} {}: {}",
+                self.path.to_string_lossy(),
+                self.line,
+                self.severity,
+                self.message
+            ),
+        }
+    }
+}
+
+/// Collects [`Issue`]s of a playlist being parsed, and logs them.
+struct Report {
+    path: PathBuf,
+    issues: Vec<Issue>,
+    wallpapers: Vec<(PathBuf, usize, String)>,
+}
+
+impl Report {
+    fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            issues: vec![],
+            wallpapers: vec![],
+        }
+    }
+
+    fn push(&mut self, line: usize, severity: Severity, message: String, skipped: bool) {
+        let issue = Issue {
+            path: self.path.clone(),
+            line,
+            severity,
+            message,
+            diagnostic: None,
+            skipped,
+        };
+        log::warn!("{issue}");
+        self.issues.push(issue);
+    }
+
+    fn error(&mut self, line: usize, message: impl Into<String>) {
+        self.push(line, Severity::Error, message.into(), false);
+    }
+
+    /// Reports an error that leaves the line out.
+    fn skip(&mut self, line: usize, message: impl Into<String>) {
+        self.push(line, Severity::Error, message.into(), true);
+    }
+
+    fn warning(&mut self, line: usize, message: impl Into<String>) {
+        self.push(line, Severity::Warning, message.into(), false);
+    }
+
+    fn syntax(&mut self, diagnostic: Diagnostic) {
+        let issue = Issue {
+            path: self.path.clone(),
+            line: diagnostic.line,
+            severity: Severity::Error,
+            message: diagnostic.to_string(),
+            diagnostic: Some(diagnostic),
+            skipped: true,
+        };
+        log::warn!("{issue}");
+        self.issues.push(issue);
+    }
 }
 
 /// Parses a playlist file and generates a list of [`Command`]s.
 /// If the playlist does not any valid [`Command`], return [`None`] instead.
-pub fn parse(path: &Path, file: &File) -> Option<Vec<Command>> {
-    let result: Vec<Command> = BufReader::new(file)
+///
+/// Header directives are taken out into the returned [`Playlist`].
+/// Other playlists are inlined in place of `include` commands,
+/// and line numbers and labels in `goto` commands are resolved to indices into the returned list.
+pub fn parse(path: &Path, file: &File) -> Option<Playlist> {
+    let result = check(path, file);
+    if result.commands.is_empty() {
+        None
+    } else {
+        Some(result)
+    }
+}
+
+/// Parses a playlist file like [`parse`], but also returns a [`Playlist`] without any valid
+/// [`Command`], so that its [`Issue`]s can be seen.
+pub fn check(path: &Path, file: &File) -> Playlist {
+    let mut including = vec![identify(path)];
+    parse_file(path, file, &mut including)
+}
+
+/// A [`Command`] with where it comes from, used during parsing.
+#[derive(Debug, PartialEq)]
+struct Entry {
+    line_no: usize,
+    /// Weight given as `<weight>: `, only meaningful in blocks picking entries at random.
+    weight: Option<u32>,
+    cmd: Command,
+}
+
+/// Does the real parsing.
+///
+/// `including` holds identities of playlists currently being parsed,
+/// the last one being the playlist of `path`.
+fn parse_file(path: &Path, file: &File, including: &mut Vec<PathBuf>) -> Playlist {
+    let mut report = Report::new(path);
+    let lines = read_lines(&mut report, file);
+    let lines = expand_variables(&mut report, lines);
+    check_lines(&mut report, &lines);
+    let mut lines = lines.into_iter();
+    let entries = read_block(&mut report, &mut lines, including, None);
+    let mut playlist = Playlist::default();
+    let entries = take_headers(&mut report, entries, &mut playlist);
+    playlist.commands = link(&mut report, entries, including, true)
+        .into_iter()
+        .map(|entry| entry.cmd)
+        .collect();
+    playlist.issues = report.issues;
+    playlist.wallpapers = report.wallpapers;
+    playlist.sort_issues();
+    playlist
+}
+
+/// Parses each non-empty line, reports and skips those that fail.
+fn read_lines(report: &mut Report, file: &File) -> Vec<(usize, Option<u32>, Line)> {
+    BufReader::new(file)
         .lines()
         .enumerate()
         .filter_map(|(line_no, line)| match line {
@@ -51,47 +322,502 @@ pub fn parse(path: &Path, file: &File) -> Option<Vec<Command>> {
                 if trimmed.is_empty() || trimmed.starts_with('#') {
                     None
                 } else {
-                    match Command::from_str(trimmed) {
-                        Ok(cmd) => Some(cmd),
+                    match parse_weighted_line(trimmed) {
+                        Ok((weight, line)) => Some((line_no + 1, weight, line)),
                         Err(err) => {
-                            log::warn!(
-                                "{}:{} error: {}, skipping",
-                                path.to_string_lossy(),
-                                line_no + 1,
-                                err
-                            );
+                            let indent = text.chars().take_while(|c| c.is_whitespace()).count();
+                            report.syntax(err.relocate(line_no + 1, &text, indent));
                             None
                         }
                     }
                 }
             }
             Err(err) => {
-                log::warn!(
-                    "{}:{} error: {}, skipping",
-                    path.to_string_lossy(),
-                    line_no + 1,
-                    err,
-                );
+                report.skip(line_no + 1, format!("{err}, skipping"));
                 None
             }
         })
+        .collect()
+}
+
+/// Takes out `set` commands, and expands variables in wallpaper ids and property values.
+///
+/// Variables are looked up among those set on earlier lines, then in the environment.
+/// Lines using an undefined variable are reported and skipped.
+fn expand_variables(
+    report: &mut Report,
+    lines: Vec<(usize, Option<u32>, Line)>,
+) -> Vec<(usize, Option<u32>, Line)> {
+    let mut vars = HashMap::new();
+    lines
+        .into_iter()
+        .filter_map(|(line_no, weight, line)| {
+            let expanded = match line {
+                Line::Command(Command::Set(name, value)) => expand(&value, &vars).map(|value| {
+                    vars.insert(name, value);
+                    None
+                }),
+                Line::Command(cmd) => expand_command(cmd, &vars).map(Some),
+                line => Ok(Some(line)),
+            };
+            match expanded {
+                Ok(line) => line.map(|line| (line_no, weight, line)),
+                Err(err) => {
+                    report.skip(line_no, format!("{err}, skipping"));
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Expands variables in a [`Command`], see [`expand`].
+fn expand_command(cmd: Command, vars: &HashMap<String, String>) -> Result<Line, String> {
+    let expand_props = |props: HashMap<String, String>| {
+        props
+            .into_iter()
+            .map(|(key, value)| Ok((key, expand(&value, vars)?)))
+            .collect::<Result<HashMap<_, _>, String>>()
+    };
+    let expand_overrides = |props: HashMap<String, Option<String>>| {
+        props
+            .into_iter()
+            .map(|(key, value)| Ok((key, value.map(|value| expand(&value, vars)).transpose()?)))
+            .collect::<Result<HashMap<_, _>, String>>()
+    };
+    let cmd = match cmd {
+        Command::Wallpaper(id, duration, props) => {
+            Command::Wallpaper(expand(&id, vars)?, duration, expand_overrides(props)?)
+        }
+        Command::Default(props) => Command::Default(expand_props(props)?),
+        Command::UpdateDefault(props) => Command::UpdateDefault(expand_overrides(props)?),
+        Command::Dir(path, duration, order, props) => Command::Dir(
+            PathBuf::from(expand(&path.to_string_lossy(), vars)?),
+            duration,
+            order,
+            expand_props(props)?,
+        ),
+        Command::Query(criteria, duration, props) => Command::Query(
+            criteria
+                .into_iter()
+                .map(|criterion| {
+                    Ok(match criterion {
+                        Criterion::Tag(tag) => Criterion::Tag(expand(&tag, vars)?),
+                        Criterion::Type(kind) => Criterion::Type(expand(&kind, vars)?),
+                        Criterion::Title(text) => Criterion::Title(expand(&text, vars)?),
+                    })
+                })
+                .collect::<Result<_, String>>()?,
+            duration,
+            expand_props(props)?,
+        ),
+        cmd => cmd,
+    };
+    Ok(Line::Command(cmd))
+}
+
+/// Replaces each `${name}` in `text` with the value of the variable.
+///
+/// This runs after quotes are removed, so variables in single-quoted text are expanded as well.
+///
+/// # Errors
+/// If a variable is neither set nor in the environment, or `${` is not closed,
+/// returns a description of the problem.
+fn expand(text: &str, vars: &HashMap<String, String>) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        result.push_str(&rest[..start]);
+        let Some(len) = rest[start + 2..].find('}') else {
+            return Err(String::from("`${` is not closed"));
+        };
+        let name = &rest[start + 2..start + 2 + len];
+        match vars.get(name) {
+            Some(value) => result.push_str(value),
+            None => match std::env::var(name) {
+                Ok(value) => result.push_str(&value),
+                Err(_) => return Err(format!("undefined variable `{name}`")),
+            },
+        }
+        rest = &rest[start + 3 + len..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Collects entries until the end of the [`Block`] opened at the given line, or the end of file.
+///
+/// Nested blocks are built into their [`Command`]s here.
+fn read_block(
+    report: &mut Report,
+    lines: &mut impl Iterator<Item = (usize, Option<u32>, Line)>,
+    including: &mut Vec<PathBuf>,
+    opened: Option<(usize, &Block)>,
+) -> Vec<Entry> {
+    let weighted = opened.is_some_and(|(_, block)| block.weighted());
+    let mut result = vec![];
+    while let Some((line_no, weight, line)) = lines.next() {
+        if weight.is_some() && !weighted {
+            report.warning(
+                line_no,
+                "weight is only meaningful in random blocks, ignoring",
+            );
+        }
+        match line {
+            Line::Command(cmd) if opened.is_some() && cmd.is_header() => {
+                report.warning(
+                    line_no,
+                    "header directives must come before other commands, skipping",
+                );
+            }
+            Line::Command(cmd) => result.push(Entry {
+                line_no,
+                weight,
+                cmd,
+            }),
+            Line::Open(block) => {
+                let entries = read_block(report, lines, including, Some((line_no, &block)));
+                let entries = link(report, entries, including, !block.weighted());
+                if entries.is_empty() {
+                    report.warning(line_no, "block has no entries");
+                }
+                let entries = entries
+                    .into_iter()
+                    .map(|entry| (entry.weight.unwrap_or(1), entry.cmd))
+                    .collect();
+                result.push(Entry {
+                    line_no,
+                    weight,
+                    cmd: block.build(entries),
+                });
+            }
+            Line::Close if opened.is_some() => return result,
+            Line::Close => {
+                report.skip(line_no, "`}` without an opening block, skipping");
+            }
+        }
+    }
+    if let Some((line_no, _)) = opened {
+        report.error(line_no, "block is not closed until the end of file");
+    }
+    result
+}
+
+/// Inlines included playlists and resolves `goto` targets in a list of entries.
+///
+/// Unless the entries are picked at random, those unreachable after `end` are reported.
+fn link(
+    report: &mut Report,
+    entries: Vec<Entry>,
+    including: &mut Vec<PathBuf>,
+    sequential: bool,
+) -> Vec<Entry> {
+    let (entries, labels) = check_labels(report, entries);
+    if sequential {
+        check_reachable(report, &entries, &labels);
+    }
+    let entries = expand_includes(report, entries, including);
+    resolve_targets(report, entries, &labels)
+}
+
+/// Takes out header directives into the [`Playlist`], they are only allowed before other commands.
+///
+/// Misplaced header directives are reported and dropped.
+fn take_headers(report: &mut Report, entries: Vec<Entry>, playlist: &mut Playlist) -> Vec<Entry> {
+    let mut in_header = true;
+    entries
+        .into_iter()
+        .filter(|entry| match &entry.cmd {
+            Command::Monitor(name) if in_header => {
+                if playlist.monitor.replace(name.clone()).is_some() {
+                    report.warning(
+                        entry.line_no,
+                        "monitor is declared again, the later one takes effect",
+                    );
+                }
+                false
+            }
+            Command::Shuffle if in_header => {
+                playlist.shuffle = true;
+                false
+            }
+            cmd if cmd.is_header() => {
+                report.warning(
+                    entry.line_no,
+                    "header directives must come before other commands, skipping",
+                );
+                false
+            }
+            _ => {
+                in_header = false;
+                true
+            }
+        })
+        .collect()
+}
+
+/// Collects lines of labels, reports and drops `goto`s with an unknown label.
+fn check_labels(report: &mut Report, entries: Vec<Entry>) -> (Vec<Entry>, HashMap<String, usize>) {
+    let mut labels = HashMap::new();
+    for entry in &entries {
+        if let Command::Label(name) = &entry.cmd
+            && labels.insert(name.clone(), entry.line_no).is_some()
+        {
+            report.warning(
+                entry.line_no,
+                format!(
+                    "label `{}` is defined again, the later one takes effect",
+                    name
+                ),
+            );
+        }
+    }
+
+    let entries = entries
+        .into_iter()
+        .filter(|entry| match &entry.cmd {
+            Command::Goto(Target::Label(name), _) if !labels.contains_key(name) => {
+                report.skip(entry.line_no, format!("unknown label `{}`, skipping", name));
+                false
+            }
+            _ => true,
+        })
         .collect();
+    (entries, labels)
+}
 
-    if result.is_empty() {
-        None
-    } else {
-        Some(result)
+/// Replaces `include` commands with the content of the included playlists.
+///
+/// Inlined commands take the line number and weight of the `include`.
+/// `goto`s in them are already resolved and get shifted to their new position.
+/// Playlists that cannot be found or include themselves are reported and skipped.
+fn expand_includes(
+    report: &mut Report,
+    entries: Vec<Entry>,
+    including: &mut Vec<PathBuf>,
+) -> Vec<Entry> {
+    let mut result = vec![];
+    for entry in entries {
+        let Command::Include(target) = &entry.cmd else {
+            result.push(entry);
+            continue;
+        };
+        let line_no = entry.line_no;
+        let found = match find_from(target, &report.path) {
+            Ok(found) => found,
+            Err(err) => {
+                report.skip(
+                    line_no,
+                    format!(
+                        "cannot include `{}`: {}, skipping",
+                        target.to_string_lossy(),
+                        err
+                    ),
+                );
+                continue;
+            }
+        };
+        let identity = identify(&found);
+        if including.contains(&identity) {
+            report.skip(
+                line_no,
+                format!("`{}` includes itself, skipping", target.to_string_lossy()),
+            );
+            continue;
+        }
+        let file = match File::open(&found) {
+            Ok(file) => file,
+            Err(err) => {
+                report.skip(
+                    line_no,
+                    format!(
+                        "cannot include `{}`: {}, skipping",
+                        target.to_string_lossy(),
+                        err
+                    ),
+                );
+                continue;
+            }
+        };
+
+        including.push(identity);
+        let included = parse_file(&found, &file, including);
+        including.pop();
+        report.issues.extend(included.issues);
+        report.wallpapers.extend(included.wallpapers);
+        if included.monitor.is_some() || included.shuffle {
+            report.warning(
+                line_no,
+                format!(
+                    "header directives in `{}` are ignored",
+                    target.to_string_lossy()
+                ),
+            );
+        }
+
+        let offset = result.len();
+        result.extend(included.commands.into_iter().map(|cmd| Entry {
+            line_no,
+            weight: entry.weight,
+            cmd: match cmd {
+                Command::Goto(Target::Index(index), times) => {
+                    Command::Goto(Target::Index(index + offset), times)
+                }
+                cmd => cmd,
+            },
+        }));
+    }
+    result
+}
+
+/// Reports lines that are probably mistakes, but still take effect.
+///
+/// These are unknown properties and zero durations.
+/// Wallpapers given by ID are also collected, to be checked against the [`Library`].
+fn check_lines(report: &mut Report, lines: &[(usize, Option<u32>, Line)]) {
+    for (line_no, _, line) in lines {
+        if let Line::Command(Command::Wallpaper(id, _, _)) = line
+            && workshop::is_id(id)
+        {
+            let path = report.path.clone();
+            report.wallpapers.push((path, *line_no, id.clone()));
+        }
+        match line {
+            Line::Command(
+                Command::Wallpaper(_, duration, _)
+                | Command::Dir(_, duration, _, _)
+                | Command::Query(_, duration, _),
+            ) if matches!(
+                duration,
+                CmdDuration::Finite(Duration::ZERO) | CmdDuration::Range(_, Duration::ZERO)
+            ) =>
+            {
+                report.warning(*line_no, "zero duration, the wallpaper is skipped");
+            }
+            _ => (),
+        }
+        match line {
+            Line::Command(Command::Wallpaper(_, _, props) | Command::UpdateDefault(props)) => {
+                check_properties(report, *line_no, props.keys());
+            }
+            Line::Command(
+                Command::Dir(_, _, _, props)
+                | Command::Query(_, _, props)
+                | Command::Default(props),
+            ) => check_properties(report, *line_no, props.keys()),
+            Line::Command(Command::Sleep(
+                CmdDuration::Finite(Duration::ZERO) | CmdDuration::Range(_, Duration::ZERO),
+            )) => {
+                report.warning(*line_no, "zero duration, the sleep does nothing");
+            }
+            _ => (),
+        }
+    }
+}
+
+fn check_properties<'a>(
+    report: &mut Report,
+    line_no: usize,
+    keys: impl Iterator<Item = &'a String>,
+) {
+    let mut unknown: Vec<&String> = keys
+        .filter(|key| !PROPERTIES.contains(&key.as_str()))
+        .collect();
+    unknown.sort();
+    for key in unknown {
+        report.warning(
+            line_no,
+            format!("unknown property `{key}`, passed to the wallpaper with `--set-property`"),
+        );
+    }
+}
+
+/// Reports entries after `end`, `unload`, `then` or an unbounded `loop` that no `goto` jumps to,
+/// once for each run of them.
+fn check_reachable(report: &mut Report, entries: &[Entry], labels: &HashMap<String, usize>) {
+    let lines: Vec<usize> = entries.iter().map(|entry| entry.line_no).collect();
+    let targets: HashSet<usize> = entries
+        .iter()
+        .filter_map(|entry| match &entry.cmd {
+            Command::Goto(Target::Line(target), _) => Some(*target),
+            Command::Goto(Target::Label(name), _) => labels.get(name).copied(),
+            _ => None,
+        })
+        .map(|target| lines.partition_point(|line| *line < target))
+        .collect();
+    let mut ended = None;
+    for (index, entry) in entries.iter().enumerate() {
+        if let Some(name) = ended
+            && !targets.contains(&index)
+        {
+            report.warning(entry.line_no, format!("unreachable after `{name}`"));
+        }
+        ended = match entry.cmd {
+            Command::End => Some("end"),
+            Command::Unload => Some("unload"),
+            Command::Then(_) => Some("then"),
+            Command::Loop(None) => Some("loop"),
+            _ => None,
+        };
+    }
+}
+
+/// Resolves line numbers and labels in `goto` commands to indices into the list.
+fn resolve_targets(
+    report: &mut Report,
+    entries: Vec<Entry>,
+    labels: &HashMap<String, usize>,
+) -> Vec<Entry> {
+    let lines: Vec<usize> = entries.iter().map(|entry| entry.line_no).collect();
+    entries
+        .into_iter()
+        .map(|entry| {
+            let cmd = match entry.cmd {
+                Command::Goto(Target::Line(target), times) => Command::Goto(
+                    Target::Index(resolve_line(report, entry.line_no, &lines, target)),
+                    times,
+                ),
+                Command::Goto(Target::Label(name), times) => Command::Goto(
+                    Target::Index(resolve_line(report, entry.line_no, &lines, labels[&name])),
+                    times,
+                ),
+                cmd => cmd,
+            };
+            Entry { cmd, ..entry }
+        })
+        .collect()
+}
+
+/// Finds the index of the first command at or after the given line.
+///
+/// If there is no such command, the index past the last command is returned, so that the
+/// [`crate::runner::Runner`] wraps back to the beginning.
+fn resolve_line(report: &mut Report, line_no: usize, lines: &[usize], target: usize) -> usize {
+    let index = lines.partition_point(|line| *line < target);
+    if index == lines.len() {
+        report.warning(
+            line_no,
+            format!("goto target line {} has no command after it", target),
+        );
     }
+    index
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::runner::CmdDuration;
-    use std::collections::HashMap;
     use std::io::Read;
     use std::time::Duration;
 
+    fn entry(line_no: usize, cmd: Command) -> Entry {
+        Entry {
+            line_no,
+            weight: None,
+            cmd,
+        }
+    }
+
     #[test]
     fn find_playlist() {
         let mut content: String = String::new();
@@ -106,7 +832,7 @@ mod tests {
     #[test]
     fn parse_playlist() {
         let playlist = PathBuf::from("../playlists/default.playlist");
-        let commands = parse(&playlist, &open(&playlist).unwrap());
+        let commands = parse(&playlist, &open(&playlist).unwrap()).map(|p| p.commands);
 
         let expected = vec![
             Command::Wallpaper(
@@ -129,4 +855,242 @@ mod tests {
         ];
         assert_eq!(commands, Some(expected));
     }
+
+    #[test]
+    fn resolve_goto() {
+        let playlist = PathBuf::from("../playlists/goto.playlist");
+        let commands = parse(&playlist, &open(&playlist).unwrap())
+            .unwrap()
+            .commands;
+        assert_eq!(commands[2], Command::Goto(Target::Index(0), Some(2)));
+        assert_eq!(commands[4], Command::Goto(Target::Index(1), Some(1)));
+        assert_eq!(commands[8], Command::Goto(Target::Index(6), Some(2)));
+
+        let lines = [1, 3, 4, 7];
+        let mut report = Report::new(Path::new(""));
+        assert_eq!(resolve_line(&mut report, 7, &lines, 2), 1);
+        assert_eq!(resolve_line(&mut report, 7, &lines, 4), 2);
+        assert_eq!(resolve_line(&mut report, 7, &lines, 9), 4);
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn header_directives() {
+        let playlist = PathBuf::from("../playlists/test.playlist");
+        let parsed = parse(&playlist, &open(&playlist).unwrap()).unwrap();
+        assert_eq!(parsed.monitor, Some("eDP-1".to_string()));
+        assert_eq!(parsed.commands.len(), 3);
+
+        let entries = vec![
+            entry(1, Command::Monitor("DP-1".to_string())),
+            entry(2, Command::Shuffle),
+            entry(3, Command::End),
+            entry(4, Command::Monitor("DP-2".to_string())),
+        ];
+        let mut playlist = Playlist::default();
+        assert_eq!(
+            take_headers(&mut Report::new(Path::new("")), entries, &mut playlist),
+            vec![entry(3, Command::End)]
+        );
+        assert_eq!(playlist.monitor, Some("DP-1".to_string()));
+        assert!(playlist.shuffle);
+    }
+
+    #[test]
+    fn include_playlist() {
+        let playlist = PathBuf::from("../playlists/include.playlist");
+        let commands = parse(&playlist, &open(&playlist).unwrap()).map(|p| p.commands);
+
+        let wallpaper = |id: &str| {
+            Command::Wallpaper(
+                id.to_string(),
+                CmdDuration::Finite(Duration::from_secs(1)),
+                HashMap::new(),
+            )
+        };
+        let expected = vec![
+            wallpaper("0"),
+            wallpaper("7"),
+            wallpaper("8"),
+            Command::Goto(Target::Index(1), Some(1)),
+            Command::Label("outro".to_string()),
+            wallpaper("9"),
+            Command::Goto(Target::Index(4), None),
+        ];
+        assert_eq!(commands, Some(expected));
+    }
+
+    #[test]
+    fn resolve_label() {
+        let entries = vec![
+            entry(1, Command::Label("intro".to_string())),
+            entry(3, Command::Sleep(CmdDuration::Infinite)),
+            entry(
+                4,
+                Command::Goto(Target::Label("intro".to_string()), Some(1)),
+            ),
+            entry(5, Command::Goto(Target::Label("nowhere".to_string()), None)),
+            entry(6, Command::Goto(Target::Label("outro".to_string()), None)),
+            entry(7, Command::Label("outro".to_string())),
+        ];
+        let mut report = Report::new(Path::new(""));
+        let (entries, labels) = check_labels(&mut report, entries);
+        let commands: Vec<Command> = resolve_targets(&mut report, entries, &labels)
+            .into_iter()
+            .map(|entry| entry.cmd)
+            .collect();
+        assert_eq!(
+            commands,
+            vec![
+                Command::Label("intro".to_string()),
+                Command::Sleep(CmdDuration::Infinite),
+                Command::Goto(Target::Index(0), Some(1)),
+                Command::Goto(Target::Index(4), None),
+                Command::Label("outro".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn variables() {
+        let playlist = PathBuf::from("../playlists/variables.playlist");
+        let commands = parse(&playlist, &open(&playlist).unwrap())
+            .unwrap()
+            .commands;
+        let mut props = HashMap::new();
+        props.insert("fps".to_string(), "30".to_string());
+        assert_eq!(
+            commands,
+            vec![
+                Command::Default(props.clone()),
+                Command::Wallpaper(
+                    "/srv/wallpapers/forest".to_string(),
+                    CmdDuration::Finite(Duration::from_secs(1)),
+                    HashMap::from([("fps".to_string(), Some("30".to_string()))])
+                )
+            ]
+        );
+
+        let mut vars = HashMap::new();
+        vars.insert("a".to_string(), "1".to_string());
+        assert_eq!(expand("${a}${a}-${a}", &vars), Ok("11-1".to_string()));
+        assert_eq!(expand("no vars", &vars), Ok("no vars".to_string()));
+        assert!(expand("${lxwengd_undefined}", &vars).is_err());
+        assert!(expand("${a", &vars).is_err());
+    }
+
+    #[test]
+    fn report_issues() {
+        let playlist = PathBuf::from("../playlists/issues.playlist");
+        let parsed = check(&playlist, &open(&playlist).unwrap());
+        let issues: Vec<(usize, Severity)> = parsed
+            .issues
+            .iter()
+            .map(|issue| (issue.line, issue.severity))
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                (2, Severity::Error),
+                (3, Severity::Warning),
+                (3, Severity::Warning),
+                (5, Severity::Error),
+                (6, Severity::Warning),
+                (8, Severity::Warning),
+                (11, Severity::Warning),
+            ]
+        );
+        assert!(parsed.has_errors());
+        let summary = parsed.summary();
+        assert!(summary.starts_with("Parsed 9 entries, skipped 2 lines\n"));
+        assert_eq!(summary.lines().count(), 1 + 4 + 6);
+
+        // An unclosed block is an error, but leaves no line out
+        let unclosed = Playlist {
+            issues: vec![Issue {
+                path: playlist.clone(),
+                line: 1,
+                severity: Severity::Error,
+                message: "block is not closed until the end of file".to_string(),
+                diagnostic: None,
+                skipped: false,
+            }],
+            ..Default::default()
+        };
+        assert!(unclosed.has_errors());
+        assert!(
+            unclosed
+                .summary()
+                .starts_with("Parsed 0 entries, skipped 0 lines\n")
+        );
+        assert_eq!(
+            parsed.issues[0].diagnostic.as_ref().map(|diag| diag.column),
+            Some(7)
+        );
+    }
+
+    #[test]
+    fn random_block() {
+        let playlist = PathBuf::from("../playlists/random.playlist");
+        let commands = parse(&playlist, &open(&playlist).unwrap()).map(|p| p.commands);
+
+        let wallpaper = |id: &str| {
+            Command::Wallpaper(
+                id.to_string(),
+                CmdDuration::Finite(Duration::from_secs(60)),
+                HashMap::new(),
+            )
+        };
+        let expected = vec![
+            wallpaper("1"),
+            Command::Choose(
+                1,
+                vec![
+                    (1, wallpaper("2")),
+                    (3, wallpaper("3")),
+                    (
+                        1,
+                        Command::Choose(2, vec![(1, wallpaper("4")), (1, wallpaper("5"))]),
+                    ),
+                ],
+            ),
+            wallpaper("6"),
+        ];
+        assert_eq!(commands, Some(expected));
+    }
+
+    #[test]
+    fn installed_wallpapers() {
+        let playlist = PathBuf::from("../playlists/installed.playlist");
+        let mut parsed = check(&playlist, &open(&playlist).unwrap());
+        let wallpapers: Vec<(usize, &str)> = parsed
+            .wallpapers
+            .iter()
+            .map(|(_, line, id)| (*line, id.as_str()))
+            .collect();
+        assert_eq!(
+            wallpapers,
+            [(2, "1001"), (3, "9999"), (5, "1002"), (6, "8888")]
+        );
+
+        parsed.check_installed(&Library::default());
+        assert!(parsed.issues.is_empty());
+
+        let library = Library::index(Path::new("../playlists/workshop")).unwrap();
+        parsed.check_installed(&library);
+        let issues: Vec<(usize, Severity, &str)> = parsed
+            .issues
+            .iter()
+            .map(|issue| (issue.line, issue.severity, issue.message.as_str()))
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                (3, Severity::Warning, "wallpaper `9999` is not installed"),
+                (6, Severity::Warning, "wallpaper `8888` is not installed")
+            ]
+        );
+        assert!(!parsed.has_errors());
+    }
 }
@@ -1,6 +1,7 @@
 //! Handles behaviours when received termination signal.
 //! Usually, this saves a file named "<playlist>.state" containing the line number of where
-//! terminted.
+//! terminted, followed by the state of the random number generator at that time,
+//! and the order of a shuffled playlist.
 //! Later, this file can be load and restore the state of a runner.
 //! This file only exists when a runner is not running, it will be removed once the runner loaded
 //! the state, regardless successfully or not.
@@ -17,22 +18,47 @@ pub enum StateError {
     LoadError,
 }
 
-/// Saves the line number to a File.
+/// Data stored for later resume.
+#[derive(Debug, Default, PartialEq)]
+pub struct SavedState {
+    /// Index of the command to resume from.
+    pub index: usize,
+    /// State of the random number generator when that command began,
+    /// so that random picks are not rolled again.
+    pub seed: Option<u64>,
+    /// Order of commands if the playlist is shuffled.
+    pub order: Option<Vec<usize>>,
+}
+
+/// Saves the state to a File.
 ///
 /// # Parameters
-/// - line: Line number.
+/// - state: The state to save.
 /// - path: Path of the playlist file.
-pub fn save_state(line: usize, path: &Path) -> Result<(), StateError> {
+pub fn save_state(state: &SavedState, path: &Path) -> Result<(), StateError> {
     let mut temp = path.to_path_buf().into_os_string();
     temp.push(".state");
 
     let mut file = std::fs::File::create(temp).map_err(|_| StateError::StoreError)?;
-    file.write_all(&line.to_be_bytes())
+    file.write_all(&state.index.to_be_bytes())
         .map_err(|_| StateError::StoreError)?;
+    if let Some(seed) = state.seed {
+        file.write_all(&seed.to_be_bytes())
+            .map_err(|_| StateError::StoreError)?;
+        if let Some(order) = &state.order {
+            file.write_all(&order.len().to_be_bytes())
+                .map_err(|_| StateError::StoreError)?;
+            for index in order {
+                file.write_all(&index.to_be_bytes())
+                    .map_err(|_| StateError::StoreError)?;
+            }
+        }
+    }
     Ok(())
 }
 
-/// Loads the line number from a File.
+/// Loads the state from a File.
+/// Files saved by older versions only contain the line number.
 ///
 /// # Parameters
 /// - path: Path of the *playlist* file, the ".resume" suffic will be added automatically.
@@ -42,7 +68,7 @@ pub fn save_state(line: usize, path: &Path) -> Result<(), StateError> {
 /// This function will check whether the stored line number exceeds the current total number of
 /// lines, which may happen if the playlist file is modified.
 /// Returns a [`ResumeError`] in this case.
-pub fn load_state(path: &Path) -> Result<usize, StateError> {
+pub fn load_state(path: &Path) -> Result<SavedState, StateError> {
     let mut temp = path.to_path_buf().into_os_string();
     temp.push(".state");
 
@@ -50,8 +76,28 @@ pub fn load_state(path: &Path) -> Result<usize, StateError> {
     let mut buffer = [0_u8; std::mem::size_of::<usize>()];
     file.read_exact(&mut buffer)
         .map_err(|_| StateError::LoadError)?;
-    let line = usize::from_be_bytes(buffer);
-    Ok(line)
+    let index = usize::from_be_bytes(buffer);
+
+    let mut buffer = [0_u8; std::mem::size_of::<u64>()];
+    let seed = file
+        .read_exact(&mut buffer)
+        .ok()
+        .map(|()| u64::from_be_bytes(buffer));
+
+    let mut buffer = [0_u8; std::mem::size_of::<usize>()];
+    let order = if file.read_exact(&mut buffer).is_ok() {
+        let len = usize::from_be_bytes(buffer);
+        let mut order = vec![];
+        for _ in 0..len {
+            file.read_exact(&mut buffer)
+                .map_err(|_| StateError::LoadError)?;
+            order.push(usize::from_be_bytes(buffer));
+        }
+        Some(order)
+    } else {
+        None
+    };
+    Ok(SavedState { index, seed, order })
 }
 
 #[cfg(test)]
@@ -61,14 +107,24 @@ mod tests {
 
     #[test]
     fn test_save() {
-        save_state(5, &PathBuf::from("save.playlist")).unwrap();
+        let state = SavedState {
+            index: 5,
+            seed: Some(42),
+            order: Some(vec![0, 2, 1]),
+        };
+        save_state(&state, &PathBuf::from("save.playlist")).unwrap();
 
         let mut file = std::fs::File::open("save.playlist.state").unwrap();
         let mut buffer = [0_u8; std::mem::size_of::<usize>()];
         file.read_exact(&mut buffer).unwrap();
-        std::fs::remove_file("save.playlist.state").unwrap();
-
         assert_eq!(usize::from_be_bytes(buffer), 5_usize);
+        let mut buffer = [0_u8; std::mem::size_of::<u64>()];
+        file.read_exact(&mut buffer).unwrap();
+        assert_eq!(u64::from_be_bytes(buffer), 42_u64);
+
+        let result = load_state(&PathBuf::from("save.playlist"));
+        std::fs::remove_file("save.playlist.state").unwrap();
+        assert_eq!(result, Ok(state));
     }
 
     #[test]
@@ -76,16 +132,21 @@ mod tests {
         let mut file = std::fs::File::create("load.playlist.state").unwrap();
         file.write_all(&8_usize.to_be_bytes()).unwrap();
 
-        let result = load_state(&PathBuf::from("load.playlist"), 10);
-        assert_eq!(result, Ok(8_usize));
+        let result = load_state(&PathBuf::from("load.playlist"));
+        std::fs::remove_file("load.playlist.state").unwrap();
+        assert_eq!(
+            result,
+            Ok(SavedState {
+                index: 8,
+                seed: None,
+                order: None
+            })
+        );
     }
 
     #[test]
-    fn test_exceed() {
-        let mut file = std::fs::File::create("exceed.playlist.state").unwrap();
-        file.write_all(&8_usize.to_be_bytes()).unwrap();
-
-        let result = load_state(&PathBuf::from("exceed.playlist"), 4);
-        assert_eq!(result, Err(StateError::ExceedMaxLine));
+    fn test_missing() {
+        let result = load_state(&PathBuf::from("missing.playlist"));
+        assert_eq!(result, Err(StateError::LoadError));
     }
 }
@@ -0,0 +1,185 @@
+//! Finds wallpapers on disk.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Where Steam keeps workshop items of Wallpaper Engine, relative to the home directory.
+pub const DEFAULT_LIBRARY: &str = ".steam/steam/steamapps/workshop/content/431960";
+
+/// What a wallpaper's `project.json` says about it.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Project {
+    pub title: String,
+    /// `scene`, `video` or `web`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub tags: Vec<String>,
+    /// Path to the preview image, resolved against the wallpaper's folder.
+    pub preview: Option<PathBuf>,
+}
+
+impl Project {
+    /// Reads the `project.json` in the given wallpaper folder.
+    ///
+    /// # Errors
+    /// If the file cannot be read or is not valid, returns an [`std::io::Error`].
+    pub fn read(dir: &Path) -> std::io::Result<Self> {
+        let content = std::fs::read_to_string(dir.join("project.json"))?;
+        let mut project: Self = serde_json::from_str(&content)?;
+        project.preview = project.preview.map(|preview| dir.join(preview));
+        Ok(project)
+    }
+}
+
+/// Wallpapers installed from the Steam workshop, keyed by their ID, which is the folder name.
+#[derive(Debug, Default)]
+pub struct Library {
+    projects: HashMap<String, Project>,
+    /// The library folder, with its modification time when indexed.
+    indexed: Option<(PathBuf, SystemTime)>,
+}
+
+impl Library {
+    /// Indexes every wallpaper in the given library folder.
+    ///
+    /// Wallpapers whose `project.json` cannot be read are logged and left out.
+    ///
+    /// # Errors
+    /// If `dir` cannot be read, returns an [`std::io::Error`].
+    pub fn index(dir: &Path) -> std::io::Result<Self> {
+        let indexed = modified(dir).map(|modified| (dir.to_path_buf(), modified));
+        let mut projects = HashMap::new();
+        for (path, _) in scan(dir)? {
+            let Some(id) = path.file_name() else {
+                continue;
+            };
+            match Project::read(&path) {
+                Ok(project) => {
+                    projects.insert(id.to_string_lossy().to_string(), project);
+                }
+                Err(err) => log::warn!("Cannot read `{}`: {err}", path.to_string_lossy()),
+            }
+        }
+        Ok(Self { projects, indexed })
+    }
+
+    /// Whether the given library folder should be indexed again, which is when it is not the
+    /// one indexed, or wallpapers have been installed or removed since.
+    pub fn is_stale(&self, dir: &Path) -> bool {
+        self.indexed.as_ref().is_none_or(|(indexed, modified)| {
+            indexed != dir || Some(*modified) != self::modified(dir)
+        })
+    }
+
+    /// Looks up an installed wallpaper.
+    pub fn get(&self, id: &str) -> Option<&Project> {
+        self.projects.get(id)
+    }
+
+    /// IDs of installed wallpapers for which `predicate` holds, sorted.
+    pub fn find(&self, predicate: impl Fn(&Project) -> bool) -> Vec<&str> {
+        let mut found: Vec<&str> = self
+            .projects
+            .iter()
+            .filter(|(_, project)| predicate(project))
+            .map(|(id, _)| id.as_str())
+            .collect();
+        found.sort_unstable();
+        found
+    }
+
+    /// The title of an installed wallpaper.
+    pub fn title(&self, id: &str) -> Option<&str> {
+        self.get(id).map(|project| project.title.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.projects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.projects.is_empty()
+    }
+}
+
+/// Whether a wallpaper is given as a workshop ID, rather than a path.
+pub fn is_id(wallpaper: &str) -> bool {
+    !wallpaper.is_empty() && wallpaper.chars().all(|c| c.is_ascii_digit())
+}
+
+/// When a file or folder was last modified, if it can be told.
+fn modified(path: &Path) -> Option<SystemTime> {
+    path.metadata()
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Lists subfolders of `dir` that contain a `project.json`, which are wallpapers,
+/// along with the time each one is modified.
+///
+/// # Errors
+/// If `dir` cannot be read, returns an [`std::io::Error`].
+pub fn scan(dir: &Path) -> std::io::Result<Vec<(PathBuf, SystemTime)>> {
+    let mut result = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.join("project.json").is_file() {
+            continue;
+        }
+        let modified = modified(&path).unwrap_or(SystemTime::UNIX_EPOCH);
+        result.push((path, modified));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_dir() {
+        let mut found: Vec<PathBuf> = scan(Path::new("../playlists/workshop"))
+            .unwrap()
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        found.sort();
+        assert_eq!(
+            found,
+            [
+                PathBuf::from("../playlists/workshop/1001"),
+                PathBuf::from("../playlists/workshop/1002")
+            ]
+        );
+        assert!(scan(Path::new("../playlists/nowhere")).is_err());
+    }
+
+    #[test]
+    fn index_library() {
+        let library = Library::index(Path::new("../playlists/workshop")).unwrap();
+        assert_eq!(library.len(), 2);
+        assert_eq!(
+            library.get("1001"),
+            Some(&Project {
+                title: "Forest".to_string(),
+                kind: "scene".to_string(),
+                tags: vec!["Nature".to_string()],
+                preview: Some(PathBuf::from("../playlists/workshop/1001/preview.jpg")),
+            })
+        );
+        assert_eq!(library.title("1002"), Some("City at Night"));
+        assert_eq!(library.title("empty"), None);
+        assert_eq!(library.title("2349470260"), None);
+
+        assert!(!library.is_stale(Path::new("../playlists/workshop")));
+        assert!(library.is_stale(Path::new("../playlists")));
+        assert!(Library::default().is_stale(Path::new("../playlists/workshop")));
+
+        assert!(is_id("2349470260"));
+        assert!(!is_id("/home/user/wallpaper"));
+        assert!(!is_id(""));
+    }
+}
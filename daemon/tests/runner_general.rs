@@ -0,0 +1,14 @@
+//! Tests general functionality of runners
+
+use std::path::Path;
+
+#[test]
+fn test() {
+    // An ordinary playlist
+    let shown = lxwengd::dry_run(Path::new("../playlists/default.playlist"), 100)
+        .expect("Failed to run the playlist");
+    assert_eq!(
+        shown,
+        ["1 for 900s", "2 for 3600s", "3 for 360s", "sleep 300s"]
+    );
+}
@@ -0,0 +1,16 @@
+//! Tests `goto` counters through a runner
+
+use std::path::Path;
+
+#[test]
+fn test() {
+    let shown = lxwengd::dry_run(Path::new("../playlists/goto.playlist"), 100)
+        .expect("Failed to run the playlist");
+
+    // Each counter is reset once its `goto` falls through, so the inner loop runs again
+    let expected: Vec<String> = [1, 2, 1, 2, 1, 2, 3, 2, 1, 2, 1, 2, 3, 4, 5, 6, 5, 6, 5, 6]
+        .iter()
+        .map(|id| format!("{id} for 1s"))
+        .collect();
+    assert_eq!(shown, expected);
+}